@@ -0,0 +1,251 @@
+use std::marker::PhantomData;
+
+use axum::{
+    extract::{Path, Query},
+    http::{header, Request, Response, StatusCode},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tower_http::validate_request::{ValidateRequest, ValidateRequestHeaderLayer};
+
+use crate::{cache, gh, util, CustomError};
+
+const LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+struct List {
+    #[serde(default)]
+    prefix: String,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+/// Exactly one of the fields picks what to purge.
+#[derive(Deserialize)]
+struct Purge {
+    path: Option<String>,
+    prefix: Option<String>,
+    glob: Option<String>,
+    #[serde(default)]
+    all: bool,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    /// `owner/repo/ref/path`, as requested below `/gh`.
+    path: String,
+    fresh: bool,
+    remaining: i64,
+    #[serde(flatten)]
+    meta: cache::Meta,
+}
+
+impl Entry {
+    fn new(meta: cache::Meta) -> Self {
+        Entry {
+            path: path(&meta).to_string(),
+            fresh: meta.is_fresh(),
+            remaining: meta.remaining(),
+            meta,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Page {
+    total: usize,
+    offset: usize,
+    limit: usize,
+    entries: Vec<Entry>,
+}
+
+#[derive(Serialize)]
+struct Oldest {
+    path: String,
+    fetched_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct Stats {
+    entries: usize,
+    bytes: u64,
+    lookups: u64,
+    hit_ratio: f64,
+    memory_ratio: f64,
+    oldest: Option<Oldest>,
+}
+
+/// Checks `Authorization` against the SHA-256 of the expected value, so the
+/// comparison takes the same time however much of the token matches.
+struct Bearer<ResBody> {
+    digest: [u8; 32],
+    _resbody: PhantomData<ResBody>,
+}
+
+impl<ResBody> Bearer<ResBody> {
+    fn new(token: &str) -> Self {
+        Bearer {
+            digest: Sha256::digest(format!("Bearer {token}")).into(),
+            _resbody: PhantomData,
+        }
+    }
+
+    fn matches(&self, authorization: &str) -> bool {
+        let digest = Sha256::digest(authorization);
+        digest
+            .iter()
+            .zip(self.digest)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+    }
+}
+
+impl<ResBody> Clone for Bearer<ResBody> {
+    fn clone(&self) -> Self {
+        Self {
+            digest: self.digest,
+            _resbody: PhantomData,
+        }
+    }
+}
+
+impl<B, ResBody> ValidateRequest<B> for Bearer<ResBody>
+where
+    ResBody: Default,
+{
+    type ResponseBody = ResBody;
+    fn validate(&mut self, request: &mut Request<B>) -> Result<(), Response<Self::ResponseBody>> {
+        if util::get_header(request.headers(), header::AUTHORIZATION)
+            .is_some_and(|authorization| self.matches(&authorization))
+        {
+            return Ok(());
+        }
+        let mut res = Response::default();
+        *res.status_mut() = StatusCode::UNAUTHORIZED;
+        Err(res)
+    }
+}
+
+/// Cache inspection and purging, behind `Authorization: Bearer <token>`.
+pub fn routes<S>(token: &str) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    assert!(!token.is_empty(), "admin.token must not be empty");
+    Router::new()
+        .route("/entries", get(list).delete(purge))
+        .route("/entries/*path", get(entry))
+        .route("/stats", get(stats))
+        .route_layer(ValidateRequestHeaderLayer::custom(Bearer::new(token)))
+}
+
+fn path(meta: &cache::Meta) -> &str {
+    let base = gh::url("");
+    meta.url.strip_prefix(base.as_str()).unwrap_or(&meta.url)
+}
+
+async fn entries() -> Vec<cache::Meta> {
+    let mut entries: Vec<cache::Meta> = cache::index::entries()
+        .await
+        .into_iter()
+        .map(|(_, meta)| meta)
+        .collect();
+    entries.sort_by(|a, b| a.url.cmp(&b.url).then(a.status.cmp(&b.status)));
+    entries
+}
+
+async fn list(Query(query): Query<List>) -> Json<Page> {
+    let limit = query.limit.unwrap_or(LIMIT).min(MAX_LIMIT);
+    let matching: Vec<cache::Meta> = entries()
+        .await
+        .into_iter()
+        .filter(|meta| path(meta).starts_with(&query.prefix))
+        .collect();
+    Json(Page {
+        total: matching.len(),
+        offset: query.offset,
+        limit,
+        entries: matching
+            .into_iter()
+            .skip(query.offset)
+            .take(limit)
+            .map(Entry::new)
+            .collect(),
+    })
+}
+
+/// The entry for a path, or its cached 404/410.
+async fn entry(Path(gh_path): Path<String>) -> Result<Json<Entry>, CustomError> {
+    let key = cache::Key::new(gh::url(gh_path.trim_start_matches('/')));
+    for key in [key.clone(), key.negative()] {
+        if let Some(meta) = cache::index::get(&key.name()).await {
+            return Ok(Json(Entry::new(meta)));
+        }
+    }
+    Err(CustomError::new("not cached", StatusCode::NOT_FOUND))
+}
+
+async fn purge(Query(query): Query<Purge>) -> Result<Json<serde_json::Value>, CustomError> {
+    let selectors = [
+        query.path.is_some(),
+        query.prefix.is_some(),
+        query.glob.is_some(),
+        query.all,
+    ];
+    if selectors.iter().filter(|&&selected| selected).count() != 1 {
+        return Err(CustomError::new(
+            "pass exactly one of path, prefix, glob or all=true",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let purged = cache::purge(|meta| {
+        let path = path(meta);
+        match (&query.path, &query.prefix, &query.glob) {
+            (Some(exact), _, _) => path == exact,
+            (_, Some(prefix), _) => path.starts_with(prefix.as_str()),
+            (_, _, Some(glob)) => util::glob(glob, path),
+            _ => query.all,
+        }
+    })
+    .await;
+    info!("admin purge: {purged} entries");
+    Ok(Json(serde_json::json!({ "purged": purged })))
+}
+
+async fn stats() -> Json<Stats> {
+    let entries = entries().await;
+    let lookups = cache::stats();
+    let oldest = entries.iter().min_by_key(|meta| meta.fetched_at);
+    Json(Stats {
+        entries: entries.len(),
+        bytes: entries.iter().map(|meta| meta.size).sum(),
+        lookups: lookups.lookups(),
+        hit_ratio: lookups.hit_ratio(),
+        memory_ratio: lookups.memory_ratio(),
+        oldest: oldest.map(|meta| Oldest {
+            path: path(meta).to_string(),
+            fetched_at: meta.fetched_at,
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_only_the_token() {
+        let bearer = Bearer::<()>::new("secret");
+        assert!(bearer.matches("Bearer secret"));
+        assert!(!bearer.matches("Bearer secreT"));
+        assert!(!bearer.matches("Bearer secret "));
+        assert!(!bearer.matches("secret"));
+        assert!(!bearer.matches("Bearer "));
+        assert!(!bearer.matches(""));
+    }
+}
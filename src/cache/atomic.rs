@@ -0,0 +1,72 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use tokio::{fs, io::AsyncWriteExt};
+
+const EXTENSION: &str = "tmp";
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A file in the cache directory that only becomes visible under its final
+/// name through [`TempFile::persist`]. Dropping it removes the temp file.
+pub struct TempFile {
+    path: PathBuf,
+    file: fs::File,
+    persisted: bool,
+}
+
+impl TempFile {
+    pub async fn create(dir: &Path) -> io::Result<Self> {
+        let name = format!(
+            ".{}-{}.{EXTENSION}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = dir.join(name);
+        let file = fs::File::create(&path).await?;
+        Ok(TempFile {
+            path,
+            file,
+            persisted: false,
+        })
+    }
+
+    pub async fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.file.write_all(buf).await
+    }
+
+    pub async fn persist(mut self, dest: &Path) -> io::Result<()> {
+        self.file.flush().await?;
+        self.file.sync_all().await?;
+        fs::rename(&self.path, dest).await?;
+        self.persisted = true;
+        if let Some(dir) = dest.parent() {
+            if let Ok(dir) = fs::File::open(dir).await {
+                dir.sync_all().await.ok();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            std::fs::remove_file(&self.path).ok();
+        }
+    }
+}
+
+pub async fn write(dest: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let dir = dest.parent().unwrap_or(Path::new("."));
+    let mut file = TempFile::create(dir).await?;
+    file.write(contents.as_ref()).await?;
+    file.persist(dest).await
+}
+
+pub fn is_temp(name: &str) -> bool {
+    matches!(super::key::extension(name), Some(EXTENSION) | Some("part"))
+}
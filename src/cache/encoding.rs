@@ -0,0 +1,128 @@
+use std::io::{self, Write};
+
+use super::{key, storage::storage, Key, Meta};
+use crate::{util, CONFIG};
+use axum::http::{header, HeaderMap, HeaderValue};
+
+/// A compressed variant of a cached body, stored next to it as
+/// `<hash>.<extension>`.
+#[derive(Debug, Clone, Copy)]
+pub enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    /// In order of preference when the client accepts several equally.
+    pub const ALL: [Encoding; 3] = [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zst",
+            Encoding::Gzip => "gz",
+        }
+    }
+
+    pub fn header(self) -> HeaderValue {
+        HeaderValue::from_static(self.name())
+    }
+
+    /// Picks the encoding with the highest `q` in the client's
+    /// `Accept-Encoding`.
+    pub fn negotiate(req: &HeaderMap) -> Option<Self> {
+        let accept = util::get_header(req, header::ACCEPT_ENCODING)?.to_lowercase();
+        let accepted: Vec<(&str, f32)> = accept
+            .split(',')
+            .map(|item| {
+                let mut params = item.split(';').map(str::trim);
+                let name = params.next().unwrap_or_default();
+                let q = params
+                    .find_map(|param| param.strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                (name, q)
+            })
+            .collect();
+        let q = |name: &str| {
+            accepted
+                .iter()
+                .find(|(accepted, _)| *accepted == name)
+                .or_else(|| accepted.iter().find(|(accepted, _)| *accepted == "*"))
+                .map_or(0.0, |(_, q)| *q)
+        };
+        let mut best = None;
+        for encoding in Encoding::ALL {
+            let q = q(encoding.name());
+            if q > 0.0 && best.is_none_or(|(_, best)| q > best) {
+                best = Some((encoding, q));
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+
+    /// Derives the variant's ETag, which must differ from the identity one.
+    pub fn etag(self, etag: &str) -> String {
+        match etag.strip_suffix('"') {
+            Some(tag) => format!("{tag}-{}\"", self.name()),
+            None => format!("{etag}-{}", self.name()),
+        }
+    }
+
+    pub fn compress(self, body: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+                encoder.write_all(body)?;
+                Ok(encoder.into_inner())
+            }
+            Encoding::Zstd => zstd::encode_all(body, 3),
+            Encoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// Whether responses for this entry depend on `Accept-Encoding`.
+pub fn varies(meta: &Meta) -> bool {
+    let content_type = meta.content_type.to_lowercase();
+    CONFIG.compress.enable
+        && CONFIG
+            .compress
+            .types
+            .iter()
+            .any(|t| content_type.contains(&t.to_lowercase()))
+}
+
+pub fn compressible(meta: &Meta) -> bool {
+    varies(meta) && meta.size >= CONFIG.compress.min_size
+}
+
+pub fn is_variant(name: &str) -> bool {
+    let extension = key::extension(name);
+    Encoding::ALL
+        .iter()
+        .any(|encoding| extension == Some(encoding.extension()))
+}
+
+pub async fn remove_variants(key: &Key) {
+    for encoding in Encoding::ALL {
+        let name = key.variant_name(encoding);
+        if let Err(e) = storage().delete(&name).await {
+            error!("{name}: {e}");
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use chrono::Utc;
+
+use super::Meta;
+use crate::config::Eviction;
+use crate::CONFIG;
+
+/// Sorts `entries` so that the first one is the next to evict under
+/// `CONFIG.cache.eviction`.
+pub fn order(entries: &mut [(String, Meta)]) {
+    let now = Utc::now();
+    let idle = |meta: &Meta| (now - meta.last_access()).num_seconds().max(0) as f64;
+    match CONFIG.cache.eviction {
+        Eviction::Lru => entries.sort_by_key(|(_, meta)| meta.last_access()),
+        Eviction::Lfu => entries.sort_by(|(_, a), (_, b)| {
+            let score = |meta: &Meta| (meta.hits + 1) as f64 / (idle(meta) / 3600.0 + 1.0);
+            score(a).total_cmp(&score(b))
+        }),
+        Eviction::Size => entries.sort_by(|(_, a), (_, b)| {
+            let score = |meta: &Meta| meta.size as f64 * (idle(meta) + 1.0);
+            score(b).total_cmp(&score(a))
+        }),
+    }
+}
@@ -0,0 +1,83 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use axum::http::StatusCode;
+use once_cell::sync::Lazy;
+use tokio::{sync::watch, time::timeout};
+
+use super::Key;
+use crate::{CustomError, CONFIG};
+
+type Flights = HashMap<String, watch::Receiver<Option<Outcome>>>;
+
+static FLIGHTS: Lazy<Mutex<Flights>> = Lazy::new(Default::default);
+
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    Cached,
+    Uncached,
+    Failed(CustomError),
+    /// The leader went away before upstream answered, e.g. because its
+    /// client disconnected; a follower should take over.
+    Aborted,
+}
+
+pub enum Flight {
+    Leader(Guard),
+    Follower(watch::Receiver<Option<Outcome>>),
+}
+
+/// Held by the one request that fetches `key` from upstream. Every other
+/// request for the same key waits on it instead of fetching again.
+pub struct Guard {
+    url: String,
+    tx: watch::Sender<Option<Outcome>>,
+}
+
+impl Guard {
+    pub fn finish(self, outcome: Outcome) {
+        FLIGHTS.lock().unwrap().remove(&self.url);
+        self.tx.send_replace(Some(outcome));
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if self.tx.borrow().is_none() {
+            FLIGHTS.lock().unwrap().remove(&self.url);
+            self.tx.send_replace(Some(Outcome::Aborted));
+        }
+    }
+}
+
+pub fn join(key: &Key) -> Flight {
+    let mut flights = FLIGHTS.lock().unwrap();
+    if let Some(rx) = flights.get(&key.url) {
+        return Flight::Follower(rx.clone());
+    }
+    let (tx, rx) = watch::channel(None);
+    flights.insert(key.url.clone(), rx);
+    Flight::Leader(Guard {
+        url: key.url.clone(),
+        tx,
+    })
+}
+
+pub async fn wait(mut rx: watch::Receiver<Option<Outcome>>) -> Outcome {
+    let wait = async {
+        loop {
+            if let Some(outcome) = rx.borrow().clone() {
+                return outcome;
+            }
+            if rx.changed().await.is_err() {
+                return Outcome::Aborted;
+            }
+        }
+    };
+    match timeout(Duration::from_secs(CONFIG.cache.wait_timeout), wait).await {
+        Ok(outcome) => outcome,
+        Err(_) => Outcome::Failed(CustomError::new(
+            "timed out waiting for upstream",
+            StatusCode::GATEWAY_TIMEOUT,
+        )),
+    }
+}
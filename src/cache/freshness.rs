@@ -0,0 +1,163 @@
+use chrono::{DateTime, Duration, Utc};
+
+use super::Meta;
+use crate::CONFIG;
+
+/// Seconds an entry stays fresh by its upstream headers, clamped to
+/// `CONFIG.cache.min_ttl` and `max_ttl`, or `CONFIG.cache.expiry` when the
+/// headers say nothing.
+pub fn ttl(meta: &Meta) -> u32 {
+    clamped(meta, CONFIG.cache.min_ttl, CONFIG.cache.max_ttl).unwrap_or(CONFIG.cache.expiry)
+}
+
+/// The upstream lifetime within `min` and `max`; no-cache stays zero.
+fn clamped(meta: &Meta, min: u32, max: Option<u32>) -> Option<u32> {
+    let directives = directives(meta.cache_control.as_deref());
+    if directives.iter().any(|(name, _)| name == "no-cache") {
+        return Some(0);
+    }
+    let lifetime = lifetime(meta, &directives)?.max(min);
+    match max {
+        Some(max) => Some(lifetime.min(max)),
+        None => Some(lifetime),
+    }
+}
+
+/// Freshness lifetime as a shared cache sees it (RFC 9111 §4.2.1):
+/// s-maxage, then max-age, then Expires, then a tenth of the time since
+/// Last-Modified. Like [`Meta::age`], it counts from when the response was
+/// generated upstream rather than when it was fetched.
+fn lifetime(meta: &Meta, directives: &[(String, Option<String>)]) -> Option<u32> {
+    let seconds = |name: &str| {
+        let (_, value) = directives.iter().find(|(n, _)| n == name)?;
+        value.as_deref()?.parse().ok()
+    };
+    if let Some(seconds) = seconds("s-maxage").or_else(|| seconds("max-age")) {
+        return Some(seconds);
+    }
+    let generated = meta.fetched_at - Duration::seconds(meta.age as i64);
+    if let Some(expires) = &meta.expires {
+        // An Expires that does not parse means already expired.
+        let expires = http_date(expires).unwrap_or(generated);
+        return Some(seconds_between(generated, expires));
+    }
+    let last_modified = http_date(meta.last_modified.as_deref()?)?;
+    Some(seconds_between(last_modified, generated) / 10)
+}
+
+/// Whether a shared cache may keep the response at all.
+pub fn is_storable(meta: &Meta) -> bool {
+    !directives(meta.cache_control.as_deref())
+        .iter()
+        .any(|(name, _)| name == "no-store" || name == "private")
+}
+
+fn directives(cache_control: Option<&str>) -> Vec<(String, Option<String>)> {
+    cache_control
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty())
+        .map(|directive| match directive.split_once('=') {
+            Some((name, value)) => (
+                name.trim().to_lowercase(),
+                Some(value.trim().trim_matches('"').to_string()),
+            ),
+            None => (directive.to_lowercase(), None),
+        })
+        .collect()
+}
+
+fn http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+fn seconds_between(from: DateTime<Utc>, to: DateTime<Utc>) -> u32 {
+    (to - from).num_seconds().clamp(0, u32::MAX as i64) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::{self, HeaderMap, HeaderName};
+
+    use super::*;
+
+    const FETCHED_AT: &str = "Sun, 18 Oct 2026 05:00:00 GMT";
+
+    fn meta(headers: &[(HeaderName, &str)]) -> Meta {
+        let headers: HeaderMap = headers
+            .iter()
+            .map(|(name, value)| (name.clone(), value.parse().unwrap()))
+            .collect();
+        let mut meta = Meta::new("https://example.com/o/r/main/a", 200, &headers);
+        meta.fetched_at = http_date(FETCHED_AT).unwrap();
+        meta
+    }
+
+    fn lifetime_of(headers: &[(HeaderName, &str)]) -> Option<u32> {
+        clamped(&meta(headers), 0, None)
+    }
+
+    #[test]
+    fn s_maxage_wins_over_max_age_and_expires() {
+        let cache_control = (header::CACHE_CONTROL, "max-age=60, s-maxage=30");
+        let expires = (header::EXPIRES, "Sun, 18 Oct 2026 06:00:00 GMT");
+        assert_eq!(lifetime_of(&[cache_control, expires.clone()]), Some(30));
+        let cache_control = (header::CACHE_CONTROL, "public, max-age=\"60\"");
+        assert_eq!(lifetime_of(&[cache_control, expires]), Some(60));
+    }
+
+    #[test]
+    fn expires() {
+        let expires = (header::EXPIRES, "Sun, 18 Oct 2026 06:00:00 GMT");
+        assert_eq!(lifetime_of(&[expires]), Some(3600));
+        let past = (header::EXPIRES, "Sun, 18 Oct 2026 04:00:00 GMT");
+        assert_eq!(lifetime_of(&[past]), Some(0));
+        let invalid = (header::EXPIRES, "0");
+        assert_eq!(lifetime_of(&[invalid]), Some(0));
+    }
+
+    #[test]
+    fn heuristic() {
+        let last_modified = (header::LAST_MODIFIED, "Sun, 18 Oct 2026 04:00:00 GMT");
+        assert_eq!(lifetime_of(&[last_modified]), Some(360));
+        assert_eq!(lifetime_of(&[]), None);
+    }
+
+    #[test]
+    fn upstream_age_counts_against_expires_only_once() {
+        let age = (header::AGE, "600");
+        let expires = (header::EXPIRES, "Sun, 18 Oct 2026 06:00:00 GMT");
+        let aged = meta(&[age.clone(), expires]);
+        assert_eq!(aged.age, 600);
+        // Meta::age adds the 600s back, leaving the hour until Expires.
+        assert_eq!(clamped(&aged, 0, None), Some(4200));
+        let max_age = (header::CACHE_CONTROL, "max-age=3600");
+        assert_eq!(lifetime_of(&[age, max_age]), Some(3600));
+    }
+
+    #[test]
+    fn no_cache_is_never_fresh() {
+        let cache_control = (header::CACHE_CONTROL, "no-cache, max-age=60");
+        assert_eq!(clamped(&meta(&[cache_control]), 300, None), Some(0));
+    }
+
+    #[test]
+    fn clamps() {
+        let cache_control = (header::CACHE_CONTROL, "max-age=60");
+        let max_age = meta(&[cache_control]);
+        assert_eq!(clamped(&max_age, 300, None), Some(300));
+        assert_eq!(clamped(&max_age, 0, Some(30)), Some(30));
+        assert_eq!(clamped(&max_age, 0, Some(3600)), Some(60));
+        assert_eq!(clamped(&meta(&[]), 300, Some(30)), None);
+    }
+
+    #[test]
+    fn storable() {
+        assert!(is_storable(&meta(&[(header::CACHE_CONTROL, "public")])));
+        assert!(!is_storable(&meta(&[(header::CACHE_CONTROL, "no-store")])));
+        assert!(!is_storable(&meta(&[(header::CACHE_CONTROL, "Private")])));
+    }
+}
@@ -0,0 +1,325 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
+
+use super::{atomic, key, meta, storage::storage, Meta};
+use crate::CONFIG;
+
+pub const NAME: &str = "index.log";
+
+static INDEX: Lazy<Mutex<Index>> = Lazy::new(Default::default);
+
+/// Hits since the last flush and when the latest one was, by entry name.
+type Touched = HashMap<String, (u64, DateTime<Utc>)>;
+
+static TOUCHED: Lazy<std::sync::Mutex<Touched>> = Lazy::new(Default::default);
+
+/// One line of the index log. The last record for a name wins; `Clean` is
+/// only ever the final line, written on an orderly shutdown.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Record {
+    Put {
+        name: String,
+        meta: Box<Meta>,
+    },
+    Touch {
+        name: String,
+        hits: u64,
+        accessed_at: DateTime<Utc>,
+    },
+    Remove {
+        name: String,
+    },
+    Clean,
+}
+
+/// Every complete cache entry, keyed by entry name and mirrored in an
+/// append-only log below `CONFIG.cache.path`, so that startup does not need
+/// to list the whole storage.
+#[derive(Default)]
+struct Index {
+    entries: HashMap<String, Meta>,
+    log: Option<fs::File>,
+    records: usize,
+    loaded: bool,
+    /// Changes made before `load` finished.
+    pending: Vec<Record>,
+}
+
+impl Index {
+    fn apply(&mut self, record: Record) {
+        match record {
+            Record::Put { name, meta } => {
+                self.entries.insert(name, *meta);
+            }
+            Record::Touch {
+                name,
+                hits,
+                accessed_at,
+            } => {
+                if let Some(meta) = self.entries.get_mut(&name) {
+                    meta.hits = hits;
+                    meta.accessed_at = Some(accessed_at);
+                }
+            }
+            Record::Remove { name } => {
+                self.entries.remove(&name);
+            }
+            Record::Clean => {}
+        }
+    }
+
+    async fn append(&mut self, record: Record) {
+        if !self.loaded {
+            self.pending.push(record);
+            return;
+        }
+        let Some(log) = self.log.as_mut() else {
+            self.apply(record);
+            return;
+        };
+        let mut line = match serde_json::to_vec(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("{NAME}: {e}");
+                return;
+            }
+        };
+        line.push(b'\n');
+        if let Err(e) = log.write_all(&line).await {
+            error!("{NAME}: {e}");
+        }
+        self.records += 1;
+        self.apply(record);
+    }
+
+    /// Adds the hits counted since the last flush to their entries.
+    async fn flush(&mut self) {
+        let touched = std::mem::take(&mut *TOUCHED.lock().unwrap());
+        for (name, (hits, accessed_at)) in touched {
+            let Some(meta) = self.entries.get(&name) else {
+                continue;
+            };
+            let hits = meta.hits + hits;
+            self.append(Record::Touch {
+                name,
+                hits,
+                accessed_at,
+            })
+            .await;
+        }
+    }
+
+    /// Rewrites the log as one `Put` per live entry.
+    async fn compact(&mut self) {
+        let path = log_path();
+        let mut content = Vec::new();
+        for (name, meta) in &self.entries {
+            let record = Record::Put {
+                name: name.clone(),
+                meta: Box::new(meta.clone()),
+            };
+            if let Ok(line) = serde_json::to_vec(&record) {
+                content.extend(line);
+                content.push(b'\n');
+            }
+        }
+        if let Err(e) = atomic::write(&path, content).await {
+            error!("{path:?}: {e}");
+            return;
+        }
+        self.records = self.entries.len();
+        self.log = match fs::OpenOptions::new().append(true).open(&path).await {
+            Ok(log) => Some(log),
+            Err(e) => {
+                error!("{path:?}: {e}");
+                None
+            }
+        };
+    }
+}
+
+fn log_path() -> PathBuf {
+    CONFIG.cache.path.join(NAME)
+}
+
+/// Replays the log, or rebuilds the index from storage when the log is
+/// missing, corrupt or was not closed cleanly.
+pub async fn load() {
+    let path = log_path();
+    let entries = match replay(&path).await {
+        Some(entries) => {
+            info!("loaded {} entries from {path:?}", entries.len());
+            entries
+        }
+        None => {
+            warn!("rebuilding {path:?}");
+            rebuild().await
+        }
+    };
+    let mut index = INDEX.lock().await;
+    let pending = std::mem::take(&mut index.pending);
+    index.entries = entries;
+    index.loaded = true;
+    for record in pending {
+        index.apply(record);
+    }
+    index.compact().await;
+}
+
+async fn replay(path: &Path) -> Option<HashMap<String, Meta>> {
+    let content = match fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                error!("{path:?}: {e}");
+            }
+            return None;
+        }
+    };
+    let mut index = Index::default();
+    let mut clean = false;
+    for line in content.lines() {
+        let record = match serde_json::from_str(line) {
+            Ok(record) => record,
+            Err(e) => {
+                error!("{path:?}: {e}");
+                return None;
+            }
+        };
+        clean = matches!(record, Record::Clean);
+        index.apply(record);
+    }
+    if !clean {
+        warn!("{path:?} was not closed cleanly");
+        return None;
+    }
+    Some(index.entries)
+}
+
+async fn rebuild() -> HashMap<String, Meta> {
+    let mut entries = HashMap::new();
+    let names = match storage().list("").await {
+        Ok(names) => names,
+        Err(e) => {
+            error!("list cache: {e}");
+            return entries;
+        }
+    };
+    for name in names.into_iter().filter(|name| key::is_entry(name)) {
+        match meta(&name).await {
+            Some(meta) => {
+                entries.insert(name, meta);
+            }
+            None => {
+                warn!("{name} has no metadata");
+                super::remove(&name).await;
+            }
+        }
+    }
+    entries
+}
+
+/// Brings the index in line with shared storage, where other instances add
+/// and remove entries that never reach this log.
+pub async fn sync() {
+    if !storage().shared() {
+        return;
+    }
+    // Taken before listing, so that entries put meanwhile are not dropped.
+    let known: HashSet<String> = INDEX.lock().await.entries.keys().cloned().collect();
+    let names = match storage().list("").await {
+        Ok(names) => names,
+        Err(e) => {
+            error!("list cache: {e}");
+            return;
+        }
+    };
+    let live: HashSet<String> = names
+        .into_iter()
+        .filter(|name| key::is_entry(name))
+        .collect();
+    for name in known.difference(&live) {
+        super::memory::remove(name);
+        remove(name).await;
+    }
+    for name in live.difference(&known) {
+        if let Some(meta) = meta(name).await {
+            put(name, &meta).await;
+        }
+    }
+}
+
+/// Records a new or refreshed entry. Hits counted so far carry over, since
+/// the metadata written by a fill or revalidation does not track them.
+pub async fn put(name: &str, meta: &Meta) {
+    let mut index = INDEX.lock().await;
+    let mut meta = meta.clone();
+    if let Some(current) = index.entries.get(name) {
+        meta.hits = meta.hits.max(current.hits);
+        meta.accessed_at = meta.accessed_at.max(current.accessed_at);
+    }
+    let record = Record::Put {
+        name: name.to_string(),
+        meta: Box::new(meta),
+    };
+    index.append(record).await;
+}
+
+pub fn touch(name: String) {
+    let now = Utc::now();
+    let mut touched = TOUCHED.lock().unwrap();
+    let (hits, accessed_at) = touched.entry(name).or_insert((0, now));
+    *hits += 1;
+    *accessed_at = now;
+}
+
+pub async fn remove(name: &str) {
+    let mut index = INDEX.lock().await;
+    if !index.loaded || index.entries.contains_key(name) {
+        let name = name.to_string();
+        index.append(Record::Remove { name }).await;
+    }
+}
+
+pub async fn get(name: &str) -> Option<Meta> {
+    INDEX.lock().await.entries.get(name).cloned()
+}
+
+/// A snapshot of every entry with its name.
+pub async fn entries() -> Vec<(String, Meta)> {
+    let index = INDEX.lock().await;
+    index
+        .entries
+        .iter()
+        .map(|(name, meta)| (name.clone(), meta.clone()))
+        .collect()
+}
+
+/// Flushes hits and compacts the log once it holds mostly superseded
+/// records.
+pub async fn maintain() {
+    let mut index = INDEX.lock().await;
+    index.flush().await;
+    if index.log.is_some() && index.records > 1024.max(index.entries.len() * 2) {
+        debug!("compacting {NAME}");
+        index.compact().await;
+    }
+}
+
+/// Marks the log as complete so the next start can trust it.
+pub async fn close() {
+    let mut index = INDEX.lock().await;
+    index.flush().await;
+    index.append(Record::Clean).await;
+    if let Some(log) = index.log.as_mut() {
+        log.sync_all().await.ok();
+    }
+}
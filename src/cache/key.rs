@@ -0,0 +1,77 @@
+use sha2::{Digest, Sha256};
+
+use super::Encoding;
+
+const NEGATIVE: &str = "negative/";
+
+/// Identifies a cache entry by the sha256 of its canonical upstream URL.
+/// Entries are sharded as `ab/cd/abcd…` in storage; cached 404/410
+/// responses live in the same layout below `negative/`.
+#[derive(Debug, Clone)]
+pub struct Key {
+    pub url: String,
+    hash: String,
+    negative: bool,
+}
+
+impl Key {
+    pub fn new(url: impl Into<String>) -> Self {
+        let url = url.into();
+        let hash = hex::encode(Sha256::digest(url.as_bytes()));
+        Key {
+            url,
+            hash,
+            negative: false,
+        }
+    }
+
+    pub fn negative(&self) -> Self {
+        Key {
+            negative: true,
+            ..self.clone()
+        }
+    }
+
+    pub fn name(&self) -> String {
+        let prefix = if self.negative { NEGATIVE } else { "" };
+        format!(
+            "{prefix}{}/{}/{}",
+            &self.hash[..2],
+            &self.hash[2..4],
+            self.hash
+        )
+    }
+
+    pub fn meta_name(&self) -> String {
+        format!("{}.{}", self.name(), super::meta::EXTENSION)
+    }
+
+    pub fn variant_name(&self, encoding: Encoding) -> String {
+        format!("{}.{}", self.name(), encoding.extension())
+    }
+}
+
+/// Whether `name` is the body of an entry, as laid out by [`Key::name`].
+pub fn is_entry(name: &str) -> bool {
+    let mut parts: Vec<&str> = name
+        .strip_prefix(NEGATIVE)
+        .unwrap_or(name)
+        .split('/')
+        .collect();
+    let Some(hash) = parts.pop() else {
+        return false;
+    };
+    hash.len() == 64
+        && hash.bytes().all(|b| b.is_ascii_hexdigit())
+        && parts == [&hash[..2], &hash[2..4]]
+}
+
+/// The name of the entry that a sidecar or variant belongs to.
+pub fn entry_name(name: &str) -> &str {
+    name.rsplit_once('.').map_or(name, |(entry, _)| entry)
+}
+
+pub fn extension(name: &str) -> Option<&str> {
+    let (_, file) = name.rsplit_once('/').unwrap_or(("", name));
+    file.rsplit_once('.').map(|(_, extension)| extension)
+}
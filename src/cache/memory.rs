@@ -0,0 +1,76 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use once_cell::sync::Lazy;
+
+use super::{Entry, Key, Meta};
+use crate::CONFIG;
+
+static MEMORY: Lazy<Mutex<Memory>> = Lazy::new(Default::default);
+
+/// Least recently used entries of at most `CONFIG.cache.memory_object_max`
+/// bytes, up to `CONFIG.cache.memory_max` in total, keyed by entry name.
+#[derive(Default)]
+struct Memory {
+    entries: HashMap<String, (u64, Entry)>,
+    order: BTreeMap<u64, String>,
+    size: u64,
+    tick: u64,
+}
+
+impl Memory {
+    fn remove(&mut self, name: &str) -> Option<Entry> {
+        let (tick, entry) = self.entries.remove(name)?;
+        self.order.remove(&tick);
+        self.size -= entry.body.len() as u64;
+        Some(entry)
+    }
+
+    fn insert(&mut self, name: String, entry: Entry) {
+        self.remove(&name);
+        self.size += entry.body.len() as u64;
+        self.tick += 1;
+        self.order.insert(self.tick, name.clone());
+        self.entries.insert(name, (self.tick, entry));
+        while self.size > CONFIG.cache.memory_max {
+            let Some((_, name)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((_, entry)) = self.entries.remove(&name) {
+                self.size -= entry.body.len() as u64;
+            }
+        }
+    }
+}
+
+pub fn get(key: &Key) -> Option<Entry> {
+    let name = key.name();
+    let mut memory = MEMORY.lock().unwrap();
+    let entry = memory.remove(&name)?;
+    memory.insert(name, entry.clone());
+    Some(entry)
+}
+
+pub fn insert(entry: Entry) {
+    let name = entry.key.name();
+    let mut memory = MEMORY.lock().unwrap();
+    if entry.body.len() as u64 > CONFIG.cache.memory_object_max.min(CONFIG.cache.memory_max) {
+        memory.remove(&name);
+        return;
+    }
+    memory.insert(name, entry);
+}
+
+/// Replaces the metadata of a held entry whose body did not change.
+pub fn update(key: &Key, meta: &Meta) {
+    let mut memory = MEMORY.lock().unwrap();
+    if let Some((_, entry)) = memory.entries.get_mut(&key.name()) {
+        entry.meta = meta.clone();
+    }
+}
+
+pub fn remove(name: &str) {
+    MEMORY.lock().unwrap().remove(name);
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+
+use super::{freshness, rule, storage::storage};
+use crate::{util, CONFIG};
+use chrono::{DateTime, Duration, Utc};
+use reqwest::header::{self, HeaderMap};
+use serde::{Deserialize, Serialize};
+
+pub const EXTENSION: &str = "meta";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Meta {
+    pub url: String,
+    pub status: u16,
+    pub content_type: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub cache_control: Option<String>,
+    #[serde(default)]
+    pub expires: Option<String>,
+    pub fetched_at: DateTime<Utc>,
+    /// Seconds the response had already spent in upstream caches, from its
+    /// `Age` header.
+    #[serde(default)]
+    pub age: u32,
+    pub size: u64,
+    pub sha256: String,
+    /// Counted in the index only; the sidecar never has them.
+    #[serde(default)]
+    pub hits: u64,
+    #[serde(default)]
+    pub accessed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Meta {
+    pub fn new(url: impl Into<String>, status: u16, headers: &HeaderMap) -> Self {
+        Meta {
+            url: url.into(),
+            status,
+            content_type: util::get_header(headers, header::CONTENT_TYPE)
+                .unwrap_or_else(|| "application/octet-stream".to_string()),
+            etag: util::get_header(headers, header::ETAG),
+            last_modified: util::get_header(headers, header::LAST_MODIFIED),
+            cache_control: util::get_header(headers, header::CACHE_CONTROL),
+            expires: util::get_header(headers, header::EXPIRES),
+            fetched_at: Utc::now(),
+            age: upstream_age(headers),
+            size: 0,
+            sha256: String::new(),
+            hits: 0,
+            accessed_at: None,
+            headers: forwarded(headers),
+        }
+    }
+
+    /// The upstream ETag, or one derived from the body's hash.
+    pub fn etag(&self) -> String {
+        match &self.etag {
+            Some(etag) => etag.clone(),
+            None => format!("\"{}\"", self.sha256),
+        }
+    }
+
+    /// The upstream Last-Modified, or the time the body was fetched.
+    pub fn last_modified(&self) -> String {
+        match &self.last_modified {
+            Some(last_modified) => last_modified.clone(),
+            None => self
+                .fetched_at
+                .format("%a, %d %b %Y %H:%M:%S GMT")
+                .to_string(),
+        }
+    }
+
+    pub fn header_map(&self) -> HeaderMap {
+        self.headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.parse().ok()?, value.parse().ok()?)))
+            .collect()
+    }
+
+    /// Seconds the entry stays fresh after it was fetched.
+    pub fn ttl(&self) -> u32 {
+        if self.is_negative() {
+            return CONFIG.cache.negative_expiry;
+        }
+        let policy = rule::policy(&self.url);
+        match (policy.immutable, policy.ttl) {
+            (true, _) => u32::MAX,
+            (false, Some(ttl)) => ttl,
+            (false, None) => freshness::ttl(self),
+        }
+    }
+
+    pub fn is_immutable(&self) -> bool {
+        !self.is_negative() && rule::policy(&self.url).immutable
+    }
+
+    /// When the entry was last served, or fetched if it never was.
+    pub fn last_access(&self) -> DateTime<Utc> {
+        self.accessed_at.unwrap_or(self.fetched_at)
+    }
+
+    /// Time since the response was generated upstream (RFC 9111 §4.2.3).
+    pub fn age(&self) -> Duration {
+        Utc::now() - self.fetched_at + Duration::seconds(self.age as i64)
+    }
+
+    /// Seconds of freshness left, zero once stale.
+    pub fn remaining(&self) -> i64 {
+        (self.ttl() as i64 - self.age().num_seconds()).max(0)
+    }
+
+    pub fn is_fresh(&self) -> bool {
+        self.is_stale_within(0)
+    }
+
+    /// Whether the entry expired no more than `secs` seconds ago.
+    pub fn is_stale_within(&self, secs: u32) -> bool {
+        self.age() < Duration::seconds(self.ttl() as i64 + secs as i64)
+    }
+
+    pub fn is_negative(&self) -> bool {
+        is_negative(self.status)
+    }
+
+    pub fn has_validators(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+
+    /// Applies a 304 from upstream: the body is unchanged and fresh again.
+    pub fn revalidated(&mut self, headers: &HeaderMap) {
+        self.fetched_at = Utc::now();
+        self.age = upstream_age(headers);
+        if let Some(etag) = util::get_header(headers, header::ETAG) {
+            self.etag = Some(etag);
+        }
+        if let Some(last_modified) = util::get_header(headers, header::LAST_MODIFIED) {
+            self.last_modified = Some(last_modified);
+        }
+        if let Some(cache_control) = util::get_header(headers, header::CACHE_CONTROL) {
+            self.cache_control = Some(cache_control);
+        }
+        if let Some(expires) = util::get_header(headers, header::EXPIRES) {
+            self.expires = Some(expires);
+        }
+        self.headers.extend(forwarded(headers));
+    }
+
+    pub async fn read(name: &str) -> Option<Self> {
+        let content = match storage().get(name).await {
+            Ok(content) => content?,
+            Err(e) => {
+                error!("{name}: {e}");
+                return None;
+            }
+        };
+        match serde_json::from_slice(&content) {
+            Ok(meta) => Some(meta),
+            Err(e) => {
+                error!("{name}: {e}");
+                None
+            }
+        }
+    }
+
+    pub async fn write(&self, name: &str) -> std::io::Result<()> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(fields) = value.as_object_mut() {
+            fields.remove("hits");
+            fields.remove("accessed_at");
+        }
+        storage()
+            .put(name, serde_json::to_vec(&value)?.into())
+            .await
+    }
+}
+
+/// Upstream headers named in `CONFIG.forward_headers`.
+fn forwarded(headers: &HeaderMap) -> BTreeMap<String, String> {
+    CONFIG
+        .forward_headers
+        .iter()
+        .filter_map(|name| {
+            let name = name.to_lowercase();
+            let value = util::get_header(headers, name.as_str())?;
+            Some((name, value))
+        })
+        .collect()
+}
+
+fn upstream_age(headers: &HeaderMap) -> u32 {
+    util::get_header(headers, header::AGE)
+        .and_then(|age| age.parse().ok())
+        .unwrap_or(0)
+}
+
+pub fn is_negative(status: u16) -> bool {
+    matches!(status, 404 | 410)
+}
@@ -0,0 +1,290 @@
+mod atomic;
+pub mod encoding;
+mod evict;
+pub mod flight;
+mod freshness;
+pub mod index;
+mod key;
+mod memory;
+mod meta;
+pub mod rule;
+mod stats;
+mod storage;
+mod tee;
+
+use std::{collections::HashSet, path::Path};
+
+use axum::body::Bytes;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+use crate::{gh, util};
+
+use atomic::is_temp;
+use encoding::is_variant;
+use storage::storage;
+
+pub use encoding::Encoding;
+pub use evict::order as eviction_order;
+pub use freshness::is_storable;
+pub use key::Key;
+pub use meta::Meta;
+pub use stats::stats;
+pub use tee::tee;
+
+#[derive(Clone)]
+pub struct Entry {
+    pub key: Key,
+    pub meta: Meta,
+    pub body: Bytes,
+}
+
+/// Reads the entry for `key`, falling back to a cached 404/410.
+pub async fn lookup(key: &Key) -> Option<Entry> {
+    let found = match read(key).await {
+        Some(found) => Some(found),
+        None => read(&key.negative()).await,
+    };
+    stats::record(found.as_ref().map(|(_, tier)| *tier));
+    found.map(|(entry, _)| entry)
+}
+
+/// Like `lookup`, but only reads the metadata.
+pub async fn stat(key: &Key) -> Option<(Key, Meta)> {
+    for key in [key.clone(), key.negative()] {
+        let Some(meta) = Meta::read(&key.meta_name()).await else {
+            continue;
+        };
+        if let Ok(Some(size)) = storage().stat(&key.name()).await {
+            if size == meta.size {
+                return Some((key, meta));
+            }
+        }
+    }
+    None
+}
+
+/// Size of the compressed variant, if one is stored.
+pub async fn variant_size(key: &Key, encoding: Encoding) -> Option<u64> {
+    storage()
+        .stat(&key.variant_name(encoding))
+        .await
+        .ok()
+        .flatten()
+}
+
+async fn read(key: &Key) -> Option<(Entry, stats::Tier)> {
+    if let Some(entry) = memory::get(key) {
+        return Some((entry, stats::Tier::Memory));
+    }
+    let meta = Meta::read(&key.meta_name()).await?;
+    let name = key.name();
+    match storage().get(&name).await {
+        Ok(Some(body)) if hex::encode(Sha256::digest(&body)) != meta.sha256 => {
+            debug!("{name}: body and metadata disagree, refill in progress");
+            None
+        }
+        Ok(Some(body)) => {
+            let entry = Entry {
+                key: key.clone(),
+                meta,
+                body,
+            };
+            memory::insert(entry.clone());
+            Some((entry, stats::Tier::Disk))
+        }
+        Ok(None) => None,
+        Err(e) => {
+            error!("{name}: {e}");
+            None
+        }
+    }
+}
+
+/// Reads the compressed variant of `entry`, compressing and storing it on
+/// first use.
+pub async fn variant(entry: &Entry, encoding: Encoding) -> Option<Bytes> {
+    let name = entry.key.variant_name(encoding);
+    if let Ok(Some(body)) = storage().get(&name).await {
+        return Some(body);
+    }
+    let body = entry.body.clone();
+    let compressed = tokio::task::spawn_blocking(move || encoding.compress(&body))
+        .await
+        .map_err(std::io::Error::from)
+        .and_then(|compressed| compressed);
+    let compressed = match compressed {
+        Ok(compressed) => Bytes::from(compressed),
+        Err(e) => {
+            error!("{name}: {e}");
+            return None;
+        }
+    };
+    // The body may have been replaced while compressing.
+    let current = Meta::read(&entry.key.meta_name()).await;
+    if current.is_some_and(|meta| meta.sha256 == entry.meta.sha256) {
+        if let Err(e) = storage().put(&name, compressed.clone()).await {
+            error!("{name}: {e}");
+        }
+    }
+    Some(compressed)
+}
+
+fn is_meta(name: &str) -> bool {
+    key::extension(name) == Some(meta::EXTENSION)
+}
+
+/// Counts a cache hit. Hits live in the index only and reach its log in
+/// batches; the `.meta` sidecar keeps what the fill wrote.
+pub fn hit(key: &Key) {
+    index::touch(key.name());
+}
+
+/// Writes the metadata of an existing entry and updates the index.
+pub async fn save(key: &Key, meta: &Meta) {
+    if let Err(e) = meta.write(&key.meta_name()).await {
+        error!("{}: {e}", key.meta_name());
+        return;
+    }
+    memory::update(key, meta);
+    index::put(&key.name(), meta).await;
+}
+
+async fn meta(name: &str) -> Option<Meta> {
+    Meta::read(&format!("{name}.{}", meta::EXTENSION)).await
+}
+
+/// Deletes the entry called `name` with its metadata and variants.
+pub async fn remove(name: &str) {
+    memory::remove(name);
+    index::remove(name).await;
+    let names = std::iter::once(name.to_string())
+        .chain(Encoding::ALL.map(|encoding| format!("{name}.{}", encoding.extension())))
+        .chain([format!("{name}.{}", meta::EXTENSION)]);
+    for name in names {
+        if let Err(e) = storage().delete(&name).await {
+            error!("{name}: {e}");
+        }
+    }
+}
+
+/// Deletes every entry whose metadata matches and returns how many.
+pub async fn purge(matches: impl Fn(&Meta) -> bool) -> usize {
+    index::sync().await;
+    let mut purged = 0;
+    for (name, meta) in index::entries().await {
+        if matches(&meta) {
+            remove(&name).await;
+            purged += 1;
+        }
+    }
+    purged
+}
+
+/// Removes what an interrupted write can leave behind: temp files, and
+/// `.meta` sidecars or compressed variants whose body is gone.
+pub async fn sweep() {
+    let names = match storage().list("").await {
+        Ok(names) => names,
+        Err(e) => {
+            error!("list cache: {e}");
+            return;
+        }
+    };
+    let entries: HashSet<&str> = names
+        .iter()
+        .map(String::as_str)
+        .filter(|name| key::is_entry(name))
+        .collect();
+    for name in &names {
+        let orphan = if is_temp(name) {
+            true
+        } else if is_meta(name) || is_variant(name) {
+            let entry = key::entry_name(name);
+            key::is_entry(entry) && !entries.contains(entry)
+        } else {
+            false
+        };
+        if orphan {
+            warn!("remove orphaned {name}");
+            storage().delete(name).await.ok();
+        }
+    }
+}
+
+/// Moves entries of the old flat layout (`owner_repo_ref_path`) into the
+/// sharded layout. Only names with exactly four segments map back to a
+/// single upstream URL; the rest are dropped.
+pub async fn migrate(dir: &Path) {
+    let mut entries = match fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("{dir:?}: {e}");
+            return;
+        }
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if !entry
+            .file_type()
+            .await
+            .map(|t| t.is_file())
+            .unwrap_or(false)
+        {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == index::NAME {
+            continue;
+        }
+        if let Some(body) = name.strip_suffix(".type") {
+            let body = path.with_file_name(body.strip_suffix('.').unwrap_or(body));
+            if !fs::try_exists(body).await.unwrap_or(true) {
+                fs::remove_file(&path).await.ok();
+            }
+            continue;
+        }
+        let typepath = util::typepath(&path);
+        if is_temp(&name) || name.matches('_').count() != 3 {
+            warn!("drop legacy cache entry {name}");
+            fs::remove_file(&path).await.ok();
+            fs::remove_file(&typepath).await.ok();
+            continue;
+        }
+        let key = Key::new(gh::url(&name.replace('_', "/")));
+        let target = key.name();
+        let moved = async {
+            let content = fs::read(&path).await?;
+            let modified = entry.metadata().await?.modified()?;
+            let meta = Meta {
+                url: key.url.clone(),
+                status: 200,
+                content_type: util::content_type_typepath(&typepath).await,
+                etag: None,
+                last_modified: None,
+                cache_control: None,
+                expires: None,
+                fetched_at: modified.into(),
+                age: 0,
+                size: content.len() as u64,
+                sha256: hex::encode(Sha256::digest(&content)),
+                hits: 0,
+                accessed_at: None,
+                headers: Default::default(),
+            };
+            meta.write(&key.meta_name()).await?;
+            storage().put(&target, content.into()).await?;
+            index::put(&target, &meta).await;
+            Ok::<_, std::io::Error>(())
+        };
+        match moved.await {
+            Ok(_) => info!("migrated {name} -> {target}"),
+            Err(e) => {
+                error!("migrate {name}: {e}");
+                remove(&target).await;
+            }
+        }
+        fs::remove_file(&path).await.ok();
+        fs::remove_file(&typepath).await.ok();
+    }
+}
@@ -0,0 +1,95 @@
+use crate::config::Rule;
+use crate::{util, CONFIG};
+
+/// How the file behind a URL is cached, from the first matching rule.
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+    /// Overrides the upstream freshness when set.
+    pub ttl: Option<u32>,
+    pub immutable: bool,
+    pub max: u64,
+    pub cacheable: bool,
+}
+
+impl Policy {
+    fn new(rule: &Rule, reference: &str) -> Self {
+        Policy {
+            ttl: rule.ttl,
+            immutable: rule.immutable.unwrap_or_else(|| is_commit(reference)),
+            max: match rule.max {
+                0 => CONFIG.file_max,
+                max => max,
+            },
+            cacheable: rule.cacheable,
+        }
+    }
+}
+
+pub fn policy(url: &str) -> Policy {
+    let parts = parts(url);
+    let (_, _, reference, _) = parts;
+    match CONFIG.cache.rules.iter().find(|rule| matches(rule, parts)) {
+        Some(rule) => Policy::new(rule, reference),
+        None => Policy::new(&Rule::default(), reference),
+    }
+}
+
+/// Splits an upstream URL into owner, repo, ref and file path.
+fn parts(url: &str) -> (&str, &str, &str, &str) {
+    let path = url.splitn(4, '/').nth(3).unwrap_or_default();
+    let mut parts = path.splitn(4, '/');
+    let mut next = || parts.next().unwrap_or_default();
+    (next(), next(), next(), next())
+}
+
+fn matches(rule: &Rule, (owner, repo, reference, path): (&str, &str, &str, &str)) -> bool {
+    util::glob(&rule.owner, owner)
+        && util::glob(&rule.repo, repo)
+        && util::glob(&rule.reference, reference)
+        && util::glob(&rule.path, path)
+}
+
+/// Whether `reference` is a full commit SHA, whose files never change.
+pub fn is_commit(reference: &str) -> bool {
+    reference.len() == 40 && reference.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_urls() {
+        assert_eq!(
+            parts("https://raw.githubusercontent.com/o/r/main/docs/a.md"),
+            ("o", "r", "main", "docs/a.md")
+        );
+        assert_eq!(
+            parts("https://raw.githubusercontent.com/o/r"),
+            ("o", "r", "", "")
+        );
+    }
+
+    #[test]
+    fn matches_every_part() {
+        let rule = Rule {
+            owner: "o".to_string(),
+            reference: "v*".to_string(),
+            path: "**/*.md".to_string(),
+            ..Rule::default()
+        };
+        assert!(matches(&rule, ("o", "r", "v1", "a.md")));
+        assert!(matches(&rule, ("o", "any", "v1", "docs/a.md")));
+        assert!(!matches(&rule, ("p", "r", "v1", "a.md")));
+        assert!(!matches(&rule, ("o", "r", "main", "a.md")));
+        assert!(!matches(&rule, ("o", "r", "v1", "a.txt")));
+    }
+
+    #[test]
+    fn recognizes_commits() {
+        assert!(is_commit("0123456789abcdef0123456789ABCDEF01234567"));
+        assert!(!is_commit("0123456789abcdef"));
+        assert!(!is_commit("main"));
+        assert!(!is_commit("g123456789abcdef0123456789abcdef01234567"));
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+static MEMORY: AtomicU64 = AtomicU64::new(0);
+static DISK: AtomicU64 = AtomicU64::new(0);
+static MISS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tier {
+    Memory,
+    Disk,
+}
+
+/// Lookups answered by each tier since startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub memory: u64,
+    pub disk: u64,
+    pub miss: u64,
+}
+
+impl Stats {
+    pub fn lookups(&self) -> u64 {
+        self.memory + self.disk + self.miss
+    }
+
+    /// Share of all lookups answered from either tier.
+    pub fn hit_ratio(&self) -> f64 {
+        ratio(self.memory + self.disk, self.lookups())
+    }
+
+    pub fn memory_ratio(&self) -> f64 {
+        ratio(self.memory, self.lookups())
+    }
+
+    /// Share of the lookups that reached the disk tier.
+    pub fn disk_ratio(&self) -> f64 {
+        ratio(self.disk, self.disk + self.miss)
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "memory {}/{} ({:.1}%), disk {}/{} ({:.1}%), hit ratio {:.1}%",
+            self.memory,
+            self.lookups(),
+            self.memory_ratio() * 100.0,
+            self.disk,
+            self.disk + self.miss,
+            self.disk_ratio() * 100.0,
+            self.hit_ratio() * 100.0,
+        )
+    }
+}
+
+fn ratio(n: u64, total: u64) -> f64 {
+    match total {
+        0 => 0.0,
+        total => n as f64 / total as f64,
+    }
+}
+
+pub fn record(tier: Option<Tier>) {
+    let counter = match tier {
+        Some(Tier::Memory) => &MEMORY,
+        Some(Tier::Disk) => &DISK,
+        None => &MISS,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn stats() -> Stats {
+    Stats {
+        memory: MEMORY.load(Ordering::Relaxed),
+        disk: DISK.load(Ordering::Relaxed),
+        miss: MISS.load(Ordering::Relaxed),
+    }
+}
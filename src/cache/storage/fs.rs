@@ -0,0 +1,126 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use futures_util::StreamExt;
+use tokio::fs;
+use tokio_util::io::ReaderStream;
+
+use super::{ByteStream, Storage, Writer};
+use crate::cache::atomic::{self, TempFile};
+
+/// Objects as files below `root`, written through a temp file and rename.
+pub struct Fs {
+    root: PathBuf,
+}
+
+impl Fs {
+    pub fn new(root: PathBuf) -> Self {
+        Fs { root }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+
+    async fn dir(&self, name: &str) -> io::Result<PathBuf> {
+        let path = self.path(name);
+        let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        fs::create_dir_all(&dir).await?;
+        Ok(dir)
+    }
+}
+
+fn not_found<T>(res: io::Result<T>) -> io::Result<Option<T>> {
+    match res {
+        Ok(t) => Ok(Some(t)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[async_trait]
+impl Storage for Fs {
+    async fn get(&self, name: &str) -> io::Result<Option<Bytes>> {
+        Ok(not_found(fs::read(self.path(name)).await)?.map(Bytes::from))
+    }
+
+    async fn stream(&self, name: &str) -> io::Result<Option<ByteStream>> {
+        let file = not_found(fs::File::open(self.path(name)).await)?;
+        Ok(file.map(|file| ReaderStream::new(file).boxed()))
+    }
+
+    async fn put(&self, name: &str, body: Bytes) -> io::Result<()> {
+        self.dir(name).await?;
+        atomic::write(&self.path(name), body).await
+    }
+
+    async fn writer(&self, name: &str) -> io::Result<Box<dyn Writer + '_>> {
+        let dir = self.dir(name).await?;
+        Ok(Box::new(FsWriter {
+            file: TempFile::create(&dir).await?,
+            dest: self.path(name),
+        }))
+    }
+
+    async fn stat(&self, name: &str) -> io::Result<Option<u64>> {
+        let metadata = not_found(fs::metadata(self.path(name)).await)?;
+        Ok(metadata.filter(|m| m.is_file()).map(|m| m.len()))
+    }
+
+    async fn delete(&self, name: &str) -> io::Result<()> {
+        not_found(fs::remove_file(self.path(name)).await).map(|_| ())
+    }
+
+    async fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let start = match prefix.rsplit_once('/') {
+            Some((dir, _)) => self.root.join(dir),
+            None => self.root.clone(),
+        };
+        let mut names = Vec::new();
+        let mut dirs = vec![start];
+        while let Some(dir) = dirs.pop() {
+            let Some(mut entries) = not_found(fs::read_dir(&dir).await)? else {
+                continue;
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let file_type = entry.file_type().await?;
+                if file_type.is_dir() {
+                    dirs.push(entry.path());
+                    continue;
+                }
+                let Ok(name) = entry.path().strip_prefix(&self.root).map(|p| {
+                    p.components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/")
+                }) else {
+                    continue;
+                };
+                if file_type.is_file() && name.starts_with(prefix) {
+                    names.push(name);
+                }
+            }
+        }
+        Ok(names)
+    }
+}
+
+struct FsWriter {
+    file: TempFile,
+    dest: PathBuf,
+}
+
+#[async_trait]
+impl Writer for FsWriter {
+    async fn write(&mut self, chunk: &[u8]) -> io::Result<()> {
+        self.file.write(chunk).await
+    }
+
+    async fn commit(self: Box<Self>) -> io::Result<()> {
+        self.file.persist(&self.dest).await
+    }
+}
@@ -0,0 +1,107 @@
+use std::{collections::BTreeMap, io, sync::Mutex};
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use futures_util::{stream, StreamExt};
+
+use super::{Buffered, ByteStream, Storage, Writer};
+
+/// Objects held in process memory, for tests and throwaway instances.
+#[derive(Default)]
+pub struct Memory {
+    objects: Mutex<BTreeMap<String, Bytes>>,
+}
+
+#[async_trait]
+impl Storage for Memory {
+    async fn get(&self, name: &str) -> io::Result<Option<Bytes>> {
+        Ok(self.objects.lock().unwrap().get(name).cloned())
+    }
+
+    async fn stream(&self, name: &str) -> io::Result<Option<ByteStream>> {
+        let body = self.get(name).await?;
+        Ok(body.map(|body| stream::once(async { Ok(body) }).boxed()))
+    }
+
+    async fn put(&self, name: &str, body: Bytes) -> io::Result<()> {
+        self.objects.lock().unwrap().insert(name.to_string(), body);
+        Ok(())
+    }
+
+    async fn writer(&self, name: &str) -> io::Result<Box<dyn Writer + '_>> {
+        Ok(Box::new(Buffered::new(self, name)))
+    }
+
+    async fn stat(&self, name: &str) -> io::Result<Option<u64>> {
+        let objects = self.objects.lock().unwrap();
+        Ok(objects.get(name).map(|body| body.len() as u64))
+    }
+
+    async fn delete(&self, name: &str) -> io::Result<()> {
+        self.objects.lock().unwrap().remove(name);
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let objects = self.objects.lock().unwrap();
+        Ok(objects
+            .range(prefix.to_string()..)
+            .map(|(name, _)| name)
+            .take_while(|name| name.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::TryStreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn round_trip() {
+        let memory = Memory::default();
+        assert_eq!(memory.get("ab/x").await.unwrap(), None);
+        assert_eq!(memory.stat("ab/x").await.unwrap(), None);
+
+        memory.put("ab/x", Bytes::from("body")).await.unwrap();
+        assert_eq!(memory.get("ab/x").await.unwrap(), Some(Bytes::from("body")));
+        assert_eq!(memory.stat("ab/x").await.unwrap(), Some(4));
+        let chunks: Vec<Bytes> = memory
+            .stream("ab/x")
+            .await
+            .unwrap()
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(chunks.concat(), b"body");
+
+        memory.delete("ab/x").await.unwrap();
+        memory.delete("ab/x").await.unwrap();
+        assert_eq!(memory.get("ab/x").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn writer_commits() {
+        let memory = Memory::default();
+        let mut writer = memory.writer("ab/x").await.unwrap();
+        writer.write(b"bo").await.unwrap();
+        writer.write(b"dy").await.unwrap();
+        assert_eq!(memory.stat("ab/x").await.unwrap(), None);
+        writer.commit().await.unwrap();
+        assert_eq!(memory.get("ab/x").await.unwrap(), Some(Bytes::from("body")));
+    }
+
+    #[tokio::test]
+    async fn lists_by_prefix() {
+        let memory = Memory::default();
+        for name in ["ab/x", "ab/y", "abc/z", "b/x"] {
+            memory.put(name, Bytes::new()).await.unwrap();
+        }
+        assert_eq!(memory.list("ab/").await.unwrap(), ["ab/x", "ab/y"]);
+        assert_eq!(memory.list("").await.unwrap().len(), 4);
+        assert!(memory.list("c").await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,98 @@
+mod fs;
+mod memory;
+mod s3;
+
+use std::io;
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use futures_util::{stream::BoxStream, TryStreamExt};
+use once_cell::sync::Lazy;
+
+use crate::config::StorageKind;
+use crate::CONFIG;
+
+pub type ByteStream = BoxStream<'static, io::Result<Bytes>>;
+
+static STORAGE: Lazy<Box<dyn Storage>> = Lazy::new(|| match CONFIG.cache.storage {
+    StorageKind::Fs => Box::new(fs::Fs::new(CONFIG.cache.path.clone())),
+    StorageKind::Memory => Box::<memory::Memory>::default(),
+    StorageKind::S3 => Box::new(s3::S3::new(&CONFIG.cache.s3)),
+});
+
+pub fn storage() -> &'static dyn Storage {
+    STORAGE.as_ref()
+}
+
+/// Where cache objects live. Names are `/`-separated and relative to the
+/// cache root, e.g. `ab/cd/<hash>.meta`.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn get(&self, name: &str) -> io::Result<Option<Bytes>> {
+        let Some(stream) = self.stream(name).await? else {
+            return Ok(None);
+        };
+        let chunks: Vec<Bytes> = stream.try_collect().await?;
+        Ok(Some(chunks.concat().into()))
+    }
+
+    async fn stream(&self, name: &str) -> io::Result<Option<ByteStream>>;
+
+    /// Stores `body` under `name`; readers never see a partial object.
+    async fn put(&self, name: &str, body: Bytes) -> io::Result<()>;
+
+    /// Starts an object that only becomes visible on [`Writer::commit`].
+    async fn writer(&self, name: &str) -> io::Result<Box<dyn Writer + '_>>;
+
+    /// Size of the object, if it exists.
+    async fn stat(&self, name: &str) -> io::Result<Option<u64>>;
+
+    /// Removes the object; a missing one is not an error.
+    async fn delete(&self, name: &str) -> io::Result<()>;
+
+    /// Every object name starting with `prefix`.
+    async fn list(&self, prefix: &str) -> io::Result<Vec<String>>;
+
+    /// Whether other instances write to the same objects, so that a local
+    /// index can miss their entries.
+    fn shared(&self) -> bool {
+        false
+    }
+}
+
+#[async_trait]
+pub trait Writer: Send {
+    async fn write(&mut self, chunk: &[u8]) -> io::Result<()>;
+
+    async fn commit(self: Box<Self>) -> io::Result<()>;
+}
+
+/// Collects the body in memory and stores it in one `put` on commit, for
+/// backends without partial writes.
+struct Buffered<'a> {
+    storage: &'a dyn Storage,
+    name: String,
+    body: Vec<u8>,
+}
+
+impl<'a> Buffered<'a> {
+    fn new(storage: &'a dyn Storage, name: &str) -> Self {
+        Buffered {
+            storage,
+            name: name.to_string(),
+            body: Vec::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Writer for Buffered<'_> {
+    async fn write(&mut self, chunk: &[u8]) -> io::Result<()> {
+        self.body.extend_from_slice(chunk);
+        Ok(())
+    }
+
+    async fn commit(self: Box<Self>) -> io::Result<()> {
+        self.storage.put(&self.name, self.body.into()).await
+    }
+}
@@ -0,0 +1,183 @@
+use std::io;
+
+use axum::{body::Bytes, http::StatusCode};
+use futures_util::{
+    stream::{self, BoxStream},
+    Stream, StreamExt,
+};
+use sha2::{Digest, Sha256};
+use tokio::sync::{mpsc, oneshot};
+
+use super::{
+    flight::{Guard, Outcome},
+    memory,
+    storage::storage,
+    Entry, Key, Meta,
+};
+use crate::{CustomError, CONFIG};
+
+type Chunk = reqwest::Result<Bytes>;
+type Sent = io::Result<Bytes>;
+
+/// Streams `upstream` to the client while writing it to the entry for `key`.
+/// The entry only appears once the whole body has been written.
+///
+/// The fill runs at upstream speed: chunks a slow client has not taken yet
+/// are buffered, at most one cacheable body, so that requests waiting on
+/// `flight` are not held up by it. Once nothing is being cached any more the
+/// flight is finished and the client pulls the rest of upstream itself.
+///
+/// `limits` are the largest body cached and the largest one sent at all;
+/// past the first the fill is dropped, past the second the response fails.
+pub fn tee<S>(
+    upstream: S,
+    key: Key,
+    meta: Meta,
+    flight: Option<Guard>,
+    limits: (u64, u64),
+) -> impl Stream<Item = Sent>
+where
+    S: Stream<Item = Chunk> + Send + Unpin + 'static,
+{
+    let (tx, rx) = mpsc::unbounded_channel();
+    let (rest_tx, rest_rx) = oneshot::channel();
+    tokio::spawn(async move {
+        let name = key.name();
+        let outcome = match fill(upstream, key, meta, tx, limits).await {
+            Fill::Done(outcome) => outcome,
+            Fill::HandOff(upstream, total) => {
+                rest_tx.send(rest(name, upstream, total, limits.1)).ok();
+                Outcome::Uncached
+            }
+        };
+        if let Some(flight) = flight {
+            flight.finish(outcome);
+        }
+    });
+    let received = stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+    let rest = stream::once(rest_rx).filter_map(|rest| async { rest.ok() });
+    received.chain(rest.flatten())
+}
+
+/// How a fill ended.
+enum Fill<S> {
+    Done(Outcome),
+    /// Nothing is being cached but the client is still there, `total` bytes
+    /// in; it takes the rest of upstream at its own pace.
+    HandOff(S, u64),
+}
+
+fn rest<S>(name: String, upstream: S, mut total: u64, limit: u64) -> BoxStream<'static, Sent>
+where
+    S: Stream<Item = Chunk> + Send + 'static,
+{
+    upstream
+        .map(move |chunk| {
+            let chunk = chunk.map_err(io::Error::other)?;
+            total += chunk.len() as u64;
+            if total > limit {
+                warn!("{name}: file size > {limit} bytes, aborting");
+                return Err(io::Error::other(format!("file size > {limit} bytes")));
+            }
+            Ok(chunk)
+        })
+        .boxed()
+}
+
+async fn fill<S>(
+    mut upstream: S,
+    key: Key,
+    mut meta: Meta,
+    tx: mpsc::UnboundedSender<Sent>,
+    (max, limit): (u64, u64),
+) -> Fill<S>
+where
+    S: Stream<Item = Chunk> + Unpin,
+{
+    let name = key.name();
+    let mut file = match storage().writer(&name).await {
+        Ok(file) => Some(file),
+        Err(e) => {
+            error!("{name}: {e}");
+            None
+        }
+    };
+    let mut hasher = Sha256::new();
+    let mut held = Some(Vec::new());
+    let mut client = true;
+    let mut total = 0;
+    while let Some(chunk) = upstream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                error!("{name}: upstream error: {e}");
+                let err = CustomError::new(e.to_string(), StatusCode::BAD_GATEWAY);
+                tx.send(Err(io::Error::other(e))).ok();
+                return Fill::Done(Outcome::Failed(err));
+            }
+        };
+        total += chunk.len() as u64;
+        if total > limit {
+            warn!("{name}: file size > {limit} bytes, aborting");
+            tx.send(Err(io::Error::other(format!("file size > {limit} bytes"))))
+                .ok();
+            return Fill::Done(Outcome::Uncached);
+        }
+        if total > max && file.take().is_some() {
+            warn!("{name} is not cached: file size > {max} bytes");
+        }
+        if let Some(f) = file.as_mut() {
+            hasher.update(&chunk);
+            meta.size += chunk.len() as u64;
+            held = held
+                .take()
+                .filter(|_| meta.size <= CONFIG.cache.memory_object_max)
+                .map(|mut held| {
+                    held.extend_from_slice(&chunk);
+                    held
+                });
+            if let Err(e) = f.write(&chunk).await {
+                error!("{name}: {e}");
+                file = None;
+            }
+        }
+        if client && tx.send(Ok(chunk)).is_err() {
+            debug!("{name}: client went away, finishing cache fill");
+            client = false;
+        }
+        if file.is_none() {
+            return match client {
+                true => Fill::HandOff(upstream, total),
+                false => Fill::Done(Outcome::Uncached),
+            };
+        }
+    }
+    let Some(file) = file else {
+        return Fill::Done(Outcome::Uncached);
+    };
+    meta.sha256 = hex::encode(hasher.finalize());
+    // Body first: until the new metadata lands, readers see it paired with
+    // the old one and drop the entry on the sha256 mismatch.
+    if let Err(e) = file.commit().await {
+        error!("{name}: {e}");
+        return Fill::Done(Outcome::Uncached);
+    }
+    if let Err(e) = meta.write(&key.meta_name()).await {
+        error!("{name}: {e}");
+        super::remove(&name).await;
+        return Fill::Done(Outcome::Uncached);
+    }
+    super::encoding::remove_variants(&key).await;
+    super::index::put(&name, &meta).await;
+    match held {
+        Some(body) => memory::insert(Entry {
+            key,
+            meta,
+            body: body.into(),
+        }),
+        None => memory::remove(&name),
+    }
+    Fill::Done(Outcome::Cached)
+}
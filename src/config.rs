@@ -9,23 +9,18 @@ use is_terminal::IsTerminal;
 use once_cell::sync::Lazy;
 use serde::{de::Visitor, Deserialize, Deserializer};
 
-const PREFIX: &'static str = "SIMPLE_GH_";
+const PREFIX: &str = "SIMPLE_GH_";
 
-pub static CONFIG: Lazy<Config> = Lazy::new(|| init_config());
+pub static CONFIG: Lazy<Config> = Lazy::new(init_config);
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub enum LogStyle {
+    #[default]
     Auto,
     Always,
     Never,
 }
 
-impl Default for LogStyle {
-    fn default() -> Self {
-        Self::Auto
-    }
-}
-
 impl LogStyle {
     pub fn is_color(&self) -> bool {
         match self {
@@ -54,6 +49,153 @@ impl<'de> Deserialize<'de> for LogStyle {
     }
 }
 
+/// Which entries go first once the cache outgrows `cache.max`.
+#[derive(Debug, Default, Clone, Copy)]
+pub enum Eviction {
+    /// Least recently used.
+    #[default]
+    Lru,
+    /// Fewest hits, weighted by how long the entry has been idle.
+    Lfu,
+    /// Largest idle entries: size times time since last use.
+    Size,
+}
+
+impl<'de> Deserialize<'de> for Eviction {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?.to_lowercase();
+        match s.as_str() {
+            "lru" => Ok(Eviction::Lru),
+            "lfu" => Ok(Eviction::Lfu),
+            "size" => Ok(Eviction::Size),
+            _ => Err(serde::de::Error::unknown_field(&s, &["lru", "lfu", "size"])),
+        }
+    }
+}
+
+/// Where cache entries are stored.
+#[derive(Debug, Default, Clone, Copy)]
+pub enum StorageKind {
+    /// Files below `cache.path`.
+    #[default]
+    Fs,
+    /// Process memory; lost on restart.
+    Memory,
+    /// An S3-compatible bucket configured in `cache.s3`.
+    S3,
+}
+
+impl<'de> Deserialize<'de> for StorageKind {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?.to_lowercase();
+        match s.as_str() {
+            "fs" => Ok(StorageKind::Fs),
+            "memory" => Ok(StorageKind::Memory),
+            "s3" => Ok(StorageKind::S3),
+            _ => Err(serde::de::Error::unknown_field(&s, &["fs", "memory", "s3"])),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct S3 {
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    /// Prepended to every object name, e.g. `simple-gh/`.
+    pub prefix: String,
+    /// `endpoint/bucket/…` rather than `bucket.endpoint/…`.
+    pub path_style: bool,
+}
+
+impl Default for S3 {
+    fn default() -> Self {
+        S3 {
+            endpoint: S3::endpoint(),
+            bucket: String::new(),
+            region: S3::region(),
+            access_key: String::new(),
+            secret_key: String::new(),
+            prefix: String::new(),
+            path_style: true,
+        }
+    }
+}
+
+impl fmt::Debug for S3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3")
+            .field("endpoint", &self.endpoint)
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .field("access_key", &self.access_key)
+            .field("prefix", &self.prefix)
+            .field("path_style", &self.path_style)
+            .finish_non_exhaustive()
+    }
+}
+
+impl S3 {
+    fn endpoint() -> String {
+        "http://127.0.0.1:9000".to_string()
+    }
+    fn region() -> String {
+        "us-east-1".to_string()
+    }
+}
+
+/// Caching policy for the files it matches; the first matching rule wins.
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Rule {
+    /// Globs over the parts of `owner/repo/ref/path`; `*` stays within a
+    /// path segment and `**` crosses them.
+    pub owner: String,
+    pub repo: String,
+    #[serde(rename = "ref")]
+    pub reference: String,
+    pub path: String,
+    /// Seconds of freshness, overriding what upstream says.
+    pub ttl: Option<u32>,
+    /// Never expires and is served with `Cache-Control: immutable`; by
+    /// default only files at a full commit SHA are.
+    pub immutable: Option<bool>,
+    /// Largest file cached, `file_max` when 0.
+    #[serde(deserialize_with = "deserialize_with_size")]
+    pub max: u64,
+    pub cacheable: bool,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule {
+            owner: Rule::any(),
+            repo: Rule::any(),
+            reference: Rule::any(),
+            path: "**".to_string(),
+            ttl: None,
+            immutable: None,
+            max: 0,
+            cacheable: true,
+        }
+    }
+}
+
+impl Rule {
+    fn any() -> String {
+        "*".to_string()
+    }
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(default)]
 pub struct Log {
@@ -80,17 +222,51 @@ impl Log {
 #[serde(default)]
 pub struct Cache {
     pub path: PathBuf,
+    pub storage: StorageKind,
+    pub s3: S3,
     #[serde(deserialize_with = "deserialize_with_size")]
     pub max: u64,
+    pub eviction: Eviction,
+    /// Bytes of small, hot entries also kept in memory; 0 disables it.
+    #[serde(deserialize_with = "deserialize_with_size")]
+    pub memory_max: u64,
+    #[serde(deserialize_with = "deserialize_with_size")]
+    pub memory_object_max: u64,
+    /// Seconds of freshness when upstream sends no Cache-Control, Expires
+    /// or Last-Modified.
     pub expiry: u32,
+    /// Bounds on the freshness upstream asks for.
+    pub min_ttl: u32,
+    pub max_ttl: Option<u32>,
+    pub rules: Vec<Rule>,
+    pub negative_expiry: u32,
+    pub wait_timeout: u64,
+    pub stale_while_revalidate: u32,
+    pub stale_if_error: u32,
+    /// Directives sent ahead of the computed `max-age`; empty leaves the
+    /// upstream Cache-Control alone.
+    pub cache_control: String,
 }
 
 impl Default for Cache {
     fn default() -> Self {
         Cache {
             path: Cache::path(),
+            storage: StorageKind::default(),
+            s3: S3::default(),
             max: Cache::max(),
+            eviction: Eviction::default(),
+            memory_max: Cache::memory_max(),
+            memory_object_max: Cache::memory_object_max(),
             expiry: Cache::expiry(),
+            min_ttl: 0,
+            max_ttl: None,
+            rules: Vec::new(),
+            negative_expiry: Cache::negative_expiry(),
+            wait_timeout: Cache::wait_timeout(),
+            stale_while_revalidate: 0,
+            stale_if_error: 0,
+            cache_control: Cache::cache_control(),
         }
     }
 }
@@ -101,29 +277,173 @@ impl Cache {
     fn max() -> u64 {
         byte_unit::Byte::from_str("512MiB").unwrap().get_bytes()
     }
+    fn memory_max() -> u64 {
+        byte_unit::Byte::from_str("64MiB").unwrap().get_bytes()
+    }
+    fn memory_object_max() -> u64 {
+        byte_unit::Byte::from_str("1MiB").unwrap().get_bytes()
+    }
     fn expiry() -> u32 {
         60 * 60 * 24
     }
+    fn negative_expiry() -> u32 {
+        60
+    }
+    fn wait_timeout() -> u64 {
+        60
+    }
+    fn cache_control() -> String {
+        "public".to_string()
+    }
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Compress {
+    pub enable: bool,
+    #[serde(deserialize_with = "deserialize_with_size")]
+    pub min_size: u64,
+    /// Content types worth compressing, matched as substrings.
+    pub types: Vec<String>,
+}
+
+impl Default for Compress {
+    fn default() -> Self {
+        Compress {
+            enable: true,
+            min_size: Compress::min_size(),
+            types: Compress::types(),
+        }
+    }
+}
+
+impl Compress {
+    fn min_size() -> u64 {
+        byte_unit::Byte::from_str("1KiB").unwrap().get_bytes()
+    }
+    fn types() -> Vec<String> {
+        [
+            "text/",
+            "application/json",
+            "application/javascript",
+            "application/xml",
+            "application/yaml",
+            "application/x-yaml",
+            "application/x-sh",
+            "+json",
+            "+xml",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+}
+
+/// Serving branch and tag paths from the commit they point at.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Resolve {
+    pub enable: bool,
+    pub api: String,
+    /// Raises the GitHub API rate limit from 60 to 5000 lookups an hour.
+    pub token: Option<String>,
+    /// Seconds a resolved ref is reused before asking again.
+    pub ttl: u32,
+}
+
+impl Default for Resolve {
+    fn default() -> Self {
+        Resolve {
+            enable: false,
+            api: Resolve::api(),
+            token: None,
+            ttl: Resolve::ttl(),
+        }
+    }
+}
+
+impl fmt::Debug for Resolve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Resolve")
+            .field("enable", &self.enable)
+            .field("api", &self.api)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Resolve {
+    fn api() -> String {
+        "https://api.github.com".to_string()
+    }
+    fn ttl() -> u32 {
+        60
+    }
+}
+
+/// GitHub push webhooks at `/webhook/github`, mounted when a secret is set.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Webhook {
+    #[serde(deserialize_with = "deserialize_non_empty")]
+    pub secret: Option<String>,
+}
+
+impl fmt::Debug for Webhook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Webhook")
+            .field("secret", &self.secret.as_ref().map(|_| "…"))
+            .finish()
+    }
+}
+
+/// The cache administration API at `/admin`, mounted when a token is set.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Admin {
+    #[serde(deserialize_with = "deserialize_non_empty")]
+    pub token: Option<String>,
+}
+
+impl fmt::Debug for Admin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Admin")
+            .field("token", &self.token.as_ref().map(|_| "…"))
+            .finish()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
 pub struct Config {
     #[serde(deserialize_with = "deserialize_with_size")]
     pub file_max: u64,
+    #[serde(deserialize_with = "deserialize_with_size")]
+    pub passthrough_max: u64,
     pub token: Option<String>,
+    pub forward_headers: Vec<String>,
     pub log: Log,
     pub addr: SocketAddr,
     pub cache: Cache,
+    pub compress: Compress,
+    pub resolve: Resolve,
+    pub webhook: Webhook,
+    pub admin: Admin,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
             file_max: Config::file_max(),
+            passthrough_max: 0,
             token: None,
+            forward_headers: Config::forward_headers(),
             log: Log::default(),
             addr: Config::addr(),
             cache: Cache::default(),
+            compress: Compress::default(),
+            resolve: Resolve::default(),
+            webhook: Webhook::default(),
+            admin: Admin::default(),
         }
     }
 }
@@ -132,6 +452,17 @@ impl Config {
     fn file_max() -> u64 {
         byte_unit::Byte::from_str("24MiB").unwrap().get_bytes()
     }
+    fn forward_headers() -> Vec<String> {
+        [
+            "etag",
+            "last-modified",
+            "cache-control",
+            "content-disposition",
+            "x-github-request-id",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
     fn addr() -> SocketAddr {
         SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 3030)
     }
@@ -174,10 +505,30 @@ where
     deserializer.deserialize_any(SizeVisitor)
 }
 
+/// `SIMPLE_GH_`-prefixed env vars. A single `_` nests keys without
+/// underscores (`SIMPLE_GH_LOG_LEVEL`), `__` nests any key
+/// (`SIMPLE_GH_CACHE__STALE_IF_ERROR`, `SIMPLE_GH_CACHE__S3__ACCESS_KEY`).
+fn figment(prefix: &str) -> Figment {
+    Figment::from(Env::prefixed(prefix))
+        .merge(
+            Env::prefixed(prefix)
+                .filter(|key| !key.as_str().contains("__"))
+                .split("_"),
+        )
+        .merge(Env::prefixed(prefix).split("__"))
+}
+
+/// Treats an empty string as not set.
+fn deserialize_non_empty<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<String>::deserialize(deserializer)?;
+    Ok(value.filter(|value| !value.is_empty()))
+}
+
 pub fn init_config() -> Config {
-    let config = Figment::from(Env::prefixed(PREFIX))
-        .merge(Env::prefixed(PREFIX).split("_"))
-        .extract::<Config>();
+    let config = figment(PREFIX).extract::<Config>();
     match config {
         Ok(config) => {
             println!("{:#?}", config);
@@ -188,3 +539,66 @@ pub fn init_config() -> Config {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nests_keys_with_underscores() {
+        let vars = [
+            ("FILE_MAX", "1MiB"),
+            ("LOG_LEVEL", "simple=debug"),
+            ("CACHE__STALE_WHILE_REVALIDATE", "30"),
+            ("CACHE__STALE_IF_ERROR", "600"),
+            ("CACHE__NEGATIVE_EXPIRY", "5"),
+            ("CACHE__WAIT_TIMEOUT", "7"),
+            ("CACHE__CACHE_CONTROL", "private"),
+            ("CACHE__MEMORY_MAX", "2MiB"),
+            ("CACHE__MEMORY_OBJECT_MAX", "4KiB"),
+            ("CACHE__MIN_TTL", "10"),
+            ("CACHE__MAX_TTL", "3600"),
+            ("CACHE__S3__ACCESS_KEY", "ak"),
+            ("CACHE__S3__SECRET_KEY", "sk"),
+            ("CACHE__S3__PATH_STYLE", "false"),
+            ("COMPRESS__MIN_SIZE", "2KiB"),
+        ];
+        let prefix = "CONFIG_TEST_";
+        for (name, value) in vars {
+            std::env::set_var(format!("{prefix}{name}"), value);
+        }
+        let config: Config = figment(prefix).extract().unwrap();
+        for (name, _) in vars {
+            std::env::remove_var(format!("{prefix}{name}"));
+        }
+        assert_eq!(config.file_max, 1024 * 1024);
+        assert_eq!(config.log.level, "simple=debug");
+        assert_eq!(config.cache.stale_while_revalidate, 30);
+        assert_eq!(config.cache.stale_if_error, 600);
+        assert_eq!(config.cache.negative_expiry, 5);
+        assert_eq!(config.cache.wait_timeout, 7);
+        assert_eq!(config.cache.cache_control, "private");
+        assert_eq!(config.cache.memory_max, 2 * 1024 * 1024);
+        assert_eq!(config.cache.memory_object_max, 4 * 1024);
+        assert_eq!(config.cache.min_ttl, 10);
+        assert_eq!(config.cache.max_ttl, Some(3600));
+        assert_eq!(config.cache.s3.access_key, "ak");
+        assert_eq!(config.cache.s3.secret_key, "sk");
+        assert!(!config.cache.s3.path_style);
+        assert_eq!(config.compress.min_size, 2 * 1024);
+    }
+
+    #[test]
+    fn empty_secrets_are_unset() {
+        let prefix = "SECRET_TEST_";
+        for name in ["WEBHOOK_SECRET", "ADMIN_TOKEN"] {
+            std::env::set_var(format!("{prefix}{name}"), "");
+        }
+        let config: Config = figment(prefix).extract().unwrap();
+        for name in ["WEBHOOK_SECRET", "ADMIN_TOKEN"] {
+            std::env::remove_var(format!("{prefix}{name}"));
+        }
+        assert!(config.webhook.secret.is_none());
+        assert!(config.admin.token.is_none());
+    }
+}
@@ -4,7 +4,7 @@ use axum::{
 };
 use std::fmt::Display;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CustomError {
     reason: String,
     status: StatusCode,
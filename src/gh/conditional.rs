@@ -0,0 +1,95 @@
+use axum::http::{header, HeaderMap};
+use chrono::DateTime;
+
+use crate::util;
+
+/// Whether the client's `If-None-Match` / `If-Modified-Since` show that it
+/// already holds the representation described by `etag` and `last_modified`.
+pub fn not_modified(req: &HeaderMap, etag: Option<&str>, last_modified: Option<&str>) -> bool {
+    if let Some(if_none_match) = util::get_header(req, header::IF_NONE_MATCH) {
+        let Some(etag) = etag else {
+            return false;
+        };
+        return if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || weak(tag) == weak(etag));
+    }
+    let (Some(since), Some(last_modified)) = (
+        util::get_header(req, header::IF_MODIFIED_SINCE),
+        last_modified,
+    ) else {
+        return false;
+    };
+    match (
+        DateTime::parse_from_rfc2822(&since),
+        DateTime::parse_from_rfc2822(last_modified),
+    ) {
+        (Ok(since), Ok(last_modified)) => last_modified <= since,
+        _ => false,
+    }
+}
+
+fn weak(tag: &str) -> &str {
+    tag.trim_start_matches("W/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAST_MODIFIED: &str = "Sun, 18 Oct 2026 05:00:00 GMT";
+
+    fn request(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn if_none_match() {
+        let req = request(header::IF_NONE_MATCH, "\"a\"");
+        assert!(not_modified(&req, Some("\"a\""), None));
+        assert!(!not_modified(&req, Some("\"b\""), None));
+        assert!(!not_modified(&req, None, Some(LAST_MODIFIED)));
+        let req = request(header::IF_NONE_MATCH, "\"b\", \"a\"");
+        assert!(not_modified(&req, Some("\"a\""), None));
+        let req = request(header::IF_NONE_MATCH, "*");
+        assert!(not_modified(&req, Some("\"a\""), None));
+    }
+
+    #[test]
+    fn if_none_match_compares_weakly() {
+        let req = request(header::IF_NONE_MATCH, "W/\"a\"");
+        assert!(not_modified(&req, Some("\"a\""), None));
+        let req = request(header::IF_NONE_MATCH, "\"a\"");
+        assert!(not_modified(&req, Some("W/\"a\""), None));
+        assert!(!not_modified(&req, Some("W/\"b\""), None));
+    }
+
+    #[test]
+    fn if_none_match_wins_over_if_modified_since() {
+        let mut req = request(header::IF_NONE_MATCH, "\"b\"");
+        req.insert(header::IF_MODIFIED_SINCE, LAST_MODIFIED.parse().unwrap());
+        assert!(!not_modified(&req, Some("\"a\""), Some(LAST_MODIFIED)));
+    }
+
+    #[test]
+    fn if_modified_since() {
+        let req = request(header::IF_MODIFIED_SINCE, LAST_MODIFIED);
+        assert!(not_modified(&req, None, Some(LAST_MODIFIED)));
+        assert!(not_modified(
+            &req,
+            None,
+            Some("Sun, 18 Oct 2026 04:00:00 GMT")
+        ));
+        assert!(!not_modified(
+            &req,
+            None,
+            Some("Sun, 18 Oct 2026 06:00:00 GMT")
+        ));
+        assert!(!not_modified(&req, None, None));
+        let req = request(header::IF_MODIFIED_SINCE, "yesterday");
+        assert!(!not_modified(&req, None, Some(LAST_MODIFIED)));
+    }
+}
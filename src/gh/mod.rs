@@ -1,6 +1,9 @@
+mod conditional;
 mod extract;
 mod middleware;
+mod range;
 mod reqwest;
+mod resolve;
 mod router;
 
 use ::reqwest::Client;
@@ -10,13 +13,16 @@ use tower_http::validate_request::ValidateRequestHeaderLayer;
 
 use crate::CONFIG;
 
+pub use self::reqwest::url;
+pub use self::resolve::forget_ref;
+
 pub fn routes() -> Router<Arc<Client>> {
-    let mut get_gh = get(router::get_gh);
+    let mut gh = get(router::get_gh).head(router::head_gh);
     if let Some(token) = CONFIG.token.clone() {
         debug!("TokenLayer");
-        get_gh = get_gh.route_layer(ValidateRequestHeaderLayer::custom(middleware::Token::new(
+        gh = gh.route_layer(ValidateRequestHeaderLayer::custom(middleware::Token::new(
             token,
         )));
     }
-    Router::new().route("/*gh_path", get(get_gh))
+    Router::new().route("/*gh_path", gh)
 }
@@ -0,0 +1,232 @@
+use std::ops::Range;
+
+use axum::{
+    body::Bytes,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+};
+
+use crate::cache::Meta;
+use crate::util;
+
+const MAX_RANGES: usize = 16;
+
+pub struct Partial {
+    pub status: StatusCode,
+    pub ctype: String,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+/// Answers the request's `Range` from a cached body. `None` means the full
+/// body should be sent: no or malformed `Range`, or an `If-Range` that no
+/// longer matches.
+pub fn partial(req: &HeaderMap, meta: &Meta, body: &Bytes) -> Option<Partial> {
+    let range = util::get_header(req, header::RANGE)?;
+    if let Some(if_range) = util::get_header(req, header::IF_RANGE) {
+        if !if_range_matches(&if_range, meta) {
+            return None;
+        }
+    }
+    let len = body.len() as u64;
+    let ranges = parse(&range, len)?;
+    let mut headers = HeaderMap::new();
+    if ranges.is_empty() {
+        headers.insert(
+            header::CONTENT_RANGE,
+            format!("bytes */{len}").parse().unwrap(),
+        );
+        return Some(Partial {
+            status: StatusCode::RANGE_NOT_SATISFIABLE,
+            ctype: meta.content_type.clone(),
+            headers,
+            body: Bytes::new(),
+        });
+    }
+    if let [range] = ranges.as_slice() {
+        headers.insert(header::CONTENT_RANGE, content_range(range, len));
+        return Some(Partial {
+            status: StatusCode::PARTIAL_CONTENT,
+            ctype: meta.content_type.clone(),
+            headers,
+            body: body.slice(range.start as usize..range.end as usize),
+        });
+    }
+    let boundary = match meta.sha256.get(..32) {
+        Some(hash) => hash.to_string(),
+        None => "simple-gh-byteranges".to_string(),
+    };
+    let mut multipart = Vec::new();
+    for range in ranges.iter() {
+        multipart.extend_from_slice(
+            format!(
+                "--{boundary}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+                meta.content_type,
+                content_range(range, len).to_str().unwrap()
+            )
+            .as_bytes(),
+        );
+        multipart.extend_from_slice(&body[range.start as usize..range.end as usize]);
+        multipart.extend_from_slice(b"\r\n");
+    }
+    multipart.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+    Some(Partial {
+        status: StatusCode::PARTIAL_CONTENT,
+        ctype: format!("multipart/byteranges; boundary={boundary}"),
+        headers,
+        body: multipart.into(),
+    })
+}
+
+/// Parses a `bytes=` range set against a body of `len` bytes. Returns the
+/// satisfiable ranges, which may be empty, or `None` if the header should
+/// be ignored.
+fn parse(header: &str, len: u64) -> Option<Vec<Range<u64>>> {
+    let specs = header.trim().strip_prefix("bytes=")?;
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(str::trim) {
+        let (start, end) = spec.split_once('-')?;
+        let range = match (start.trim(), end.trim()) {
+            ("", suffix) => {
+                let suffix: u64 = suffix.parse().ok()?;
+                len.saturating_sub(suffix)..len
+            }
+            (start, "") => start.parse().ok()?..len,
+            (start, end) => {
+                let start: u64 = start.parse().ok()?;
+                let end: u64 = end.parse().ok()?;
+                if end < start {
+                    return None;
+                }
+                start..end.saturating_add(1).min(len)
+            }
+        };
+        if range.start < range.end {
+            ranges.push(range);
+        }
+    }
+    if ranges.len() > MAX_RANGES {
+        return None;
+    }
+    Some(ranges)
+}
+
+fn if_range_matches(if_range: &str, meta: &Meta) -> bool {
+    if if_range.starts_with('"') {
+        return meta.etag() == if_range;
+    }
+    if if_range.starts_with("W/") {
+        return false;
+    }
+    meta.last_modified() == if_range
+}
+
+fn content_range(range: &Range<u64>, len: u64) -> HeaderValue {
+    format!("bytes {}-{}/{len}", range.start, range.end - 1)
+        .parse()
+        .unwrap()
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    fn meta(etag: &str) -> Meta {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ETAG, etag.parse().unwrap());
+        headers.insert(
+            header::LAST_MODIFIED,
+            "Sun, 18 Oct 2026 05:00:00 GMT".parse().unwrap(),
+        );
+        headers.insert(header::CONTENT_TYPE, "text/plain".parse().unwrap());
+        let mut meta = Meta::new("https://example.com/o/r/main/f", 200, &headers);
+        meta.size = 10;
+        meta
+    }
+
+    fn request(range: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, range.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse("bytes=0-3", 10), Some(vec![0..4]));
+        assert_eq!(parse("bytes=5-", 10), Some(vec![5..10]));
+        assert_eq!(parse("bytes=-3", 10), Some(vec![7..10]));
+        assert_eq!(parse("bytes=-30", 10), Some(vec![0..10]));
+        assert_eq!(parse("bytes=8-100", 10), Some(vec![8..10]));
+        assert_eq!(parse("bytes=0-1, 4-5", 10), Some(vec![0..2, 4..6]));
+    }
+
+    #[test]
+    fn unsatisfiable_ranges_are_dropped() {
+        assert_eq!(parse("bytes=10-", 10), Some(vec![]));
+        assert_eq!(parse("bytes=20-30", 10), Some(vec![]));
+        assert_eq!(parse("bytes=-0", 10), Some(vec![]));
+        assert_eq!(parse("bytes=20-30, 2-3", 10), Some(vec![2..4]));
+    }
+
+    #[test]
+    fn malformed_ranges_are_ignored() {
+        assert_eq!(parse("items=0-3", 10), None);
+        assert_eq!(parse("bytes=3-1", 10), None);
+        assert_eq!(parse("bytes=a-b", 10), None);
+        assert_eq!(parse("bytes=5", 10), None);
+        let many = vec!["0-0"; MAX_RANGES + 1].join(",");
+        assert_eq!(parse(&format!("bytes={many}"), 10), None);
+    }
+
+    #[test]
+    fn single_range() {
+        let body = Bytes::from_static(b"0123456789");
+        let partial = partial(&request("bytes=2-4"), &meta("\"a\""), &body).unwrap();
+        assert_eq!(partial.status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(partial.body, Bytes::from_static(b"234"));
+        assert_eq!(partial.headers[header::CONTENT_RANGE], "bytes 2-4/10");
+    }
+
+    #[test]
+    fn past_the_end_is_416() {
+        let body = Bytes::from_static(b"0123456789");
+        let partial = partial(&request("bytes=10-"), &meta("\"a\""), &body).unwrap();
+        assert_eq!(partial.status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(partial.headers[header::CONTENT_RANGE], "bytes */10");
+        assert!(partial.body.is_empty());
+    }
+
+    #[test]
+    fn multiple_ranges_are_multipart() {
+        let body = Bytes::from_static(b"0123456789");
+        let partial = partial(&request("bytes=0-1,-2"), &meta("\"a\""), &body).unwrap();
+        assert_eq!(partial.status, StatusCode::PARTIAL_CONTENT);
+        assert!(partial.ctype.starts_with("multipart/byteranges; boundary="));
+        let body = String::from_utf8(partial.body.to_vec()).unwrap();
+        assert!(body.contains("Content-Range: bytes 0-1/10\r\n\r\n01\r\n"));
+        assert!(body.contains("Content-Range: bytes 8-9/10\r\n\r\n89\r\n"));
+    }
+
+    #[test]
+    fn if_range() {
+        let body = Bytes::from_static(b"0123456789");
+        let meta = meta("\"a\"");
+        let mut req = request("bytes=0-1");
+        req.insert(header::IF_RANGE, "\"a\"".parse().unwrap());
+        assert!(partial(&req, &meta, &body).is_some());
+        req.insert(header::IF_RANGE, "\"b\"".parse().unwrap());
+        assert!(partial(&req, &meta, &body).is_none());
+        req.insert(header::IF_RANGE, "W/\"a\"".parse().unwrap());
+        assert!(partial(&req, &meta, &body).is_none());
+        req.insert(
+            header::IF_RANGE,
+            "Sun, 18 Oct 2026 05:00:00 GMT".parse().unwrap(),
+        );
+        assert!(partial(&req, &meta, &body).is_some());
+        req.insert(
+            header::IF_RANGE,
+            "Sun, 18 Oct 2026 04:00:00 GMT".parse().unwrap(),
+        );
+        assert!(partial(&req, &meta, &body).is_none());
+    }
+}
@@ -1,7 +1,11 @@
 use std::sync::Arc;
 
-use reqwest::Client;
+use reqwest::{
+    header::{self, HeaderMap, HeaderName},
+    Client,
+};
 
+use crate::cache::Meta;
 use crate::CustomError;
 
 type RequestOutput = Result<reqwest::Response, CustomError>;
@@ -10,17 +14,50 @@ pub struct Request {
     url: String,
     client: Arc<Client>,
 }
+
+pub fn url(gh_path: &str) -> String {
+    format!("https://raw.githubusercontent.com/{gh_path}")
+}
+
 impl Request {
     pub fn new(client: Arc<Client>, gh_path: &str) -> Self {
         Request {
-            url: format!("https://raw.githubusercontent.com/{gh_path}"),
+            url: url(gh_path),
             client,
         }
     }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
     pub async fn get(&self) -> RequestOutput {
         Request::result(self.client.get(&self.url).send().await)
     }
 
+    /// GET carrying over the named headers from the client's request.
+    pub async fn forward(&self, headers: &HeaderMap, names: &[HeaderName]) -> RequestOutput {
+        let mut req = self.client.get(&self.url);
+        for name in names {
+            if let Some(value) = headers.get(name) {
+                req = req.header(name, value);
+            }
+        }
+        Request::result(req.send().await)
+    }
+
+    /// Conditional GET against the validators of a cached entry.
+    pub async fn revalidate(&self, meta: &Meta) -> RequestOutput {
+        let mut req = self.client.get(&self.url);
+        if let Some(etag) = &meta.etag {
+            req = req.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &meta.last_modified {
+            req = req.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+        Request::result(req.send().await)
+    }
+
     pub async fn head(&self) -> RequestOutput {
         Request::result(self.client.head(&self.url).send().await)
     }
@@ -0,0 +1,99 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use reqwest::{header, Client, StatusCode};
+
+use crate::cache::rule::is_commit;
+use crate::CONFIG;
+
+/// `owner/repo/ref` to the commit it pointed at, or to None when GitHub
+/// does not know the ref, with the time it was looked up.
+type Refs = HashMap<String, (Option<String>, Instant)>;
+
+static REFS: Lazy<Mutex<Refs>> = Lazy::new(Default::default);
+
+/// Rewrites `owner/repo/ref/path` to the commit `ref` currently points at,
+/// when `CONFIG.resolve` is enabled and the lookup succeeds.
+pub async fn resolve(client: &Client, gh_path: &str) -> (String, Option<String>) {
+    let parts: Vec<&str> = gh_path.splitn(4, '/').collect();
+    let [owner, repo, reference, path] = parts[..] else {
+        return (gh_path.to_string(), None);
+    };
+    if !CONFIG.resolve.enable || is_commit(reference) {
+        return (gh_path.to_string(), None);
+    }
+    match commit(client, owner, repo, reference).await {
+        Some(sha) => (format!("{owner}/{repo}/{sha}/{path}"), Some(sha)),
+        None => (gh_path.to_string(), None),
+    }
+}
+
+/// Drops the cached commit of a ref that has just moved.
+pub fn forget_ref(owner: &str, repo: &str, reference: &str) {
+    REFS.lock()
+        .unwrap()
+        .remove(&ref_name(owner, repo, reference));
+}
+
+fn ref_name(owner: &str, repo: &str, reference: &str) -> String {
+    format!(
+        "{}/{}/{reference}",
+        owner.to_lowercase(),
+        repo.to_lowercase()
+    )
+}
+
+async fn commit(client: &Client, owner: &str, repo: &str, reference: &str) -> Option<String> {
+    let name = ref_name(owner, repo, reference);
+    let ttl = Duration::from_secs(CONFIG.resolve.ttl.into());
+    if let Some((sha, at)) = REFS.lock().unwrap().get(&name) {
+        if at.elapsed() < ttl {
+            return sha.clone();
+        }
+    }
+    let url = format!(
+        "{}/repos/{owner}/{repo}/commits/{reference}",
+        CONFIG.resolve.api.trim_end_matches('/')
+    );
+    let mut req = client
+        .get(&url)
+        .header(header::ACCEPT, "application/vnd.github.sha")
+        .header(
+            header::USER_AGENT,
+            concat!("simple-gh/", env!("CARGO_PKG_VERSION")),
+        );
+    if let Some(token) = &CONFIG.resolve.token {
+        req = req.bearer_auth(token);
+    }
+    let sha = match req.send().await {
+        Ok(res) if res.status().is_success() => {
+            let sha = res.text().await.ok()?.trim().to_string();
+            is_commit(&sha).then_some(sha)
+        }
+        Ok(res)
+            if matches!(
+                res.status(),
+                StatusCode::NOT_FOUND | StatusCode::UNPROCESSABLE_ENTITY
+            ) =>
+        {
+            debug!("{name}: unknown ref");
+            None
+        }
+        Ok(res) => {
+            warn!("{url}: {}", res.status());
+            return None;
+        }
+        Err(e) => {
+            warn!("{url}: {e}");
+            return None;
+        }
+    };
+    let mut refs = REFS.lock().unwrap();
+    refs.retain(|_, (_, at)| at.elapsed() < ttl);
+    refs.insert(name, (sha.clone(), Instant::now()));
+    sha
+}
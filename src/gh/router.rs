@@ -1,22 +1,39 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use axum::{
+    body::{Bytes, StreamBody},
     extract::State,
-    http::{header, StatusCode},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
 };
+use futures_util::{stream::BoxStream, Stream, StreamExt};
 use reqwest::Client;
-use tokio::fs;
 
+use super::conditional;
 use super::extract::GHPath;
+use super::range;
 use super::reqwest::Request;
+use super::resolve;
 use super::CONFIG;
-use crate::util;
-use crate::CustomError;
+use crate::cache::{
+    self,
+    encoding::{self, Encoding},
+    flight::{self, Flight, Guard, Outcome},
+};
+use crate::{util, CustomError};
+
+const STALE: &str = "110 - \"Response is Stale\"";
+const REVALIDATION_FAILED: &str = "111 - \"Revalidation Failed\"";
+const YEAR: u32 = 60 * 60 * 24 * 365;
 
 struct GHResponse<T> {
+    status: StatusCode,
     body: T,
     ctype: String,
+    headers: HeaderMap,
 }
 
 impl<T> IntoResponse for GHResponse<T>
@@ -24,72 +41,578 @@ where
     T: IntoResponse,
 {
     fn into_response(self) -> Response {
-        let mut res = self.body.into_response();
+        let mut res = (self.status, self.body).into_response();
+        res.headers_mut().extend(self.headers);
         res.headers_mut()
             .insert(header::CONTENT_TYPE, self.ctype.parse().unwrap());
         res
     }
 }
 
+type Upstream = BoxStream<'static, reqwest::Result<Bytes>>;
+type Passthrough = BoxStream<'static, Result<Bytes, axum::Error>>;
+
+enum Body {
+    Cacheable(Upstream),
+    Passthrough(Passthrough, String),
+}
+
+enum Update {
+    NotModified(cache::Meta, Duration),
+    Fetched(Response),
+}
+
+#[derive(Clone, Copy)]
+enum XCache {
+    Hit,
+    Miss,
+    Stale(&'static str),
+    Revalidated,
+}
+
+impl XCache {
+    fn header(self) -> HeaderValue {
+        HeaderValue::from_static(match self {
+            XCache::Hit => "HIT",
+            XCache::Miss => "MISS",
+            XCache::Stale(_) => "STALE",
+            XCache::Revalidated => "REVALIDATED",
+        })
+    }
+}
+
 pub async fn get_gh(
     GHPath(gh_path): GHPath,
     State(client): State<Arc<Client>>,
+    headers: HeaderMap,
 ) -> Result<Response, CustomError> {
-    let filepath = gh_path.replace("/", "_");
-    let filepath = CONFIG.cache.path.join(filepath);
-    let typepath = util::typepath(&filepath);
-    match fs::read(&filepath).await {
-        Ok(content) => {
-            debug!("{filepath:?} is exists");
-            let content_type = util::content_type_typepath(&typepath).await;
-            return Ok(GHResponse {
-                body: content,
-                ctype: content_type,
-            }
-            .into_response());
+    let (gh_path, commit) = resolve::resolve(&client, &gh_path).await;
+    let res = get(Request::new(client, &gh_path), headers).await?;
+    Ok(resolved(res, commit))
+}
+
+async fn get(req: Request, headers: HeaderMap) -> Result<Response, CustomError> {
+    if !cache::rule::policy(req.url()).cacheable {
+        return uncached(&req, &headers).await;
+    }
+    let key = cache::Key::new(req.url());
+    let stale = match cache::lookup(&key).await {
+        Some(entry) if entry.meta.is_fresh() => {
+            return Ok(cached(entry, XCache::Hit, &headers).await)
         }
-        Err(e) => {
-            if e.kind() != std::io::ErrorKind::NotFound {
-                error!("{filepath:?}: {e}")
-            }
+        Some(entry) if entry.meta.is_negative() => None,
+        entry => entry,
+    };
+    if stale.is_none() && headers.contains_key(header::RANGE) {
+        return uncached(&req, &headers).await;
+    }
+    let swr = stale
+        .as_ref()
+        .filter(|entry| {
+            entry
+                .meta
+                .is_stale_within(CONFIG.cache.stale_while_revalidate)
+        })
+        .is_some();
+    let guard = loop {
+        break match flight::join(&key) {
+            Flight::Leader(guard) => Some(guard),
+            Flight::Follower(_) if swr => None,
+            Flight::Follower(rx) => match flight::wait(rx).await {
+                Outcome::Cached => match cache::lookup(&key).await {
+                    Some(entry) => return Ok(cached(entry, XCache::Hit, &headers).await),
+                    None => None,
+                },
+                Outcome::Uncached => None,
+                Outcome::Failed(e) => return stale_if_error(stale, e, &headers).await,
+                Outcome::Aborted => continue,
+            },
+        };
+    };
+    if swr {
+        let stale = stale.unwrap();
+        if guard.is_some() {
+            let entry = stale.clone();
+            tokio::spawn(async move { update(&req, &key, Some(&entry), guard).await });
+        }
+        return Ok(cached(stale, XCache::Stale(STALE), &headers).await);
+    }
+    match update(&req, &key, stale.as_ref(), guard).await {
+        Ok(Update::Fetched(res)) => Ok(not_modified(res, &headers)),
+        Ok(Update::NotModified(meta, elapsed)) => {
+            let entry = cache::Entry {
+                meta,
+                ..stale.unwrap()
+            };
+            let mut res = cached(entry, XCache::Revalidated, &headers).await;
+            res.headers_mut()
+                .insert("server-timing", server_timing(elapsed));
+            Ok(res)
+        }
+        Err(e) => stale_if_error(stale, e, &headers).await,
+    }
+}
+
+/// Answers a HEAD from the cached metadata, or with an upstream HEAD when
+/// there is no fresh entry. Bodies are never downloaded.
+pub async fn head_gh(
+    GHPath(gh_path): GHPath,
+    State(client): State<Arc<Client>>,
+    headers: HeaderMap,
+) -> Result<Response, CustomError> {
+    let (gh_path, commit) = resolve::resolve(&client, &gh_path).await;
+    let res = head(Request::new(client, &gh_path), headers).await?;
+    Ok(resolved(res, commit))
+}
+
+async fn head(req: Request, headers: HeaderMap) -> Result<Response, CustomError> {
+    let key = cache::Key::new(req.url());
+    if cache::rule::policy(req.url()).cacheable {
+        if let Some((key, meta)) = cache::stat(&key).await.filter(|(_, meta)| meta.is_fresh()) {
+            return Ok(described(key, meta, &headers).await);
         }
     }
-    let req = Request::new(client, &gh_path);
+    let start = Instant::now();
     let res = req.head().await?;
-    match res.content_length() {
-        Some(content_length) => {
-            if content_length > CONFIG.file_max {
-                let reason = format!(
-                    "file size: {} > {}",
-                    byte_unit::Byte::from_bytes(content_length)
-                        .get_appropriate_unit(true)
-                        .to_string(),
-                    byte_unit::Byte::from_bytes(CONFIG.file_max)
-                        .get_appropriate_unit(true)
-                        .to_string()
-                );
-                return Err(CustomError::new(reason, StatusCode::PAYLOAD_TOO_LARGE));
+    let status = res.status();
+    let meta = cache::Meta::new(req.url(), status.as_u16(), res.headers());
+    let mut headers = meta.header_map();
+    for name in [header::CONTENT_LENGTH, header::ETAG, header::LAST_MODIFIED] {
+        if let Some(value) = res.headers().get(&name) {
+            headers.insert(name, value.clone());
+        }
+    }
+    headers.insert("x-cache", XCache::Miss.header());
+    headers.insert("server-timing", server_timing(start.elapsed()));
+    Ok(GHResponse {
+        status,
+        body: (),
+        ctype: meta.content_type,
+        headers,
+    }
+    .into_response())
+}
+
+/// Marks a response to a branch or tag path with the commit it was served
+/// from. The entry at the commit never changes but the ref does, so clients
+/// may keep it no longer than the resolved ref is reused.
+fn resolved(mut res: Response, commit: Option<String>) -> Response {
+    let Some(commit) = commit else {
+        return res;
+    };
+    let headers = res.headers_mut();
+    if let Ok(commit) = HeaderValue::from_str(&commit) {
+        headers.insert("x-resolved-commit", commit);
+    }
+    let Some(cache_control) = util::get_header(headers, header::CACHE_CONTROL) else {
+        return res;
+    };
+    if let Ok(value) = capped(&cache_control, CONFIG.resolve.ttl).parse() {
+        headers.insert(header::CACHE_CONTROL, value);
+    }
+    res
+}
+
+/// `cache_control` with `max-age` at most `max` and without `immutable`.
+fn capped(cache_control: &str, max: u32) -> String {
+    let directives: Vec<String> = cache_control
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.eq_ignore_ascii_case("immutable"))
+        .map(|directive| {
+            let max_age = directive.strip_prefix("max-age=");
+            match max_age.and_then(|max_age| max_age.parse::<u32>().ok()) {
+                Some(max_age) => format!("max-age={}", max_age.min(max)),
+                None => directive.to_string(),
+            }
+        })
+        .collect();
+    directives.join(", ")
+}
+
+/// Headers shared by every response served from a cache entry.
+fn entry_headers(meta: &cache::Meta, x_cache: XCache) -> HeaderMap {
+    let mut headers = meta.header_map();
+    if let XCache::Stale(warning) = x_cache {
+        headers.insert(header::WARNING, HeaderValue::from_static(warning));
+    }
+    headers.insert("x-cache", x_cache.header());
+    headers.insert(header::AGE, meta.age().num_seconds().max(0).into());
+    cache_control(&mut headers, meta);
+    if meta.status == StatusCode::OK {
+        headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        if encoding::varies(meta) {
+            headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+        }
+    }
+    headers
+}
+
+async fn described(key: cache::Key, meta: cache::Meta, req: &HeaderMap) -> Response {
+    debug!("{:?} is exists", key.url);
+    let mut headers = entry_headers(&meta, XCache::Hit);
+    let mut status = StatusCode::from_u16(meta.status).unwrap_or(StatusCode::OK);
+    let mut content_length = meta.size;
+    if status == StatusCode::OK {
+        let mut etag = meta.etag();
+        let last_modified = meta.last_modified();
+        // HEAD never compresses: until a GET has stored the variant, it
+        // describes the identity body.
+        let variant = match negotiated(req, &meta, &etag) {
+            Some((encoding, etag)) => cache::variant_size(&key, encoding)
+                .await
+                .map(|size| (encoding, etag, size)),
+            None => None,
+        };
+        let served = variant.as_ref().map_or(&etag, |(_, etag, _)| etag);
+        if conditional::not_modified(req, Some(served), Some(&last_modified)) {
+            status = StatusCode::NOT_MODIFIED;
+            etag = served.clone();
+        } else if let Some((encoding, variant_etag, size)) = variant {
+            headers.insert(header::CONTENT_ENCODING, encoding.header());
+            content_length = size;
+            etag = variant_etag;
+        }
+        if let Ok(etag) = etag.parse() {
+            headers.insert(header::ETAG, etag);
+        }
+        if let Ok(last_modified) = last_modified.parse() {
+            headers.insert(header::LAST_MODIFIED, last_modified);
+        }
+    }
+    if status != StatusCode::NOT_MODIFIED {
+        headers.insert(header::CONTENT_LENGTH, content_length.into());
+    }
+    GHResponse {
+        status,
+        body: (),
+        ctype: meta.content_type,
+        headers,
+    }
+    .into_response()
+}
+
+async fn cached(entry: cache::Entry, x_cache: XCache, req: &HeaderMap) -> Response {
+    debug!("{:?} is exists", entry.key.url);
+    let mut headers = entry_headers(&entry.meta, x_cache);
+    let mut status = StatusCode::from_u16(entry.meta.status).unwrap_or(StatusCode::OK);
+    let mut content_type = entry.meta.content_type.clone();
+    let mut body = entry.body.clone();
+    if status == StatusCode::OK {
+        let mut etag = entry.meta.etag();
+        let last_modified = entry.meta.last_modified();
+        let variant = negotiated(req, &entry.meta, &etag);
+        let served = variant.as_ref().map_or(&etag, |(_, etag)| etag);
+        if conditional::not_modified(req, Some(served), Some(&last_modified)) {
+            status = StatusCode::NOT_MODIFIED;
+            body = Bytes::new();
+            etag = served.clone();
+        } else if let Some((encoding, variant_etag)) = variant {
+            if let Some(compressed) = cache::variant(&entry, encoding).await {
+                headers.insert(header::CONTENT_ENCODING, encoding.header());
+                body = compressed;
+                etag = variant_etag;
             }
+        } else if let Some(partial) = range::partial(req, &entry.meta, &body) {
+            status = partial.status;
+            content_type = partial.ctype;
+            headers.extend(partial.headers);
+            body = partial.body;
+        }
+        if let Ok(etag) = etag.parse() {
+            headers.insert(header::ETAG, etag);
+        }
+        if let Ok(last_modified) = last_modified.parse() {
+            headers.insert(header::LAST_MODIFIED, last_modified);
         }
-        None => return Err(CustomError::reason(format!("{:#?}", res.headers()))),
     }
-    let status_code = res.status();
-    let is_success = status_code.is_success();
-    let content_type = match res.headers().get(reqwest::header::CONTENT_TYPE) {
-        Some(ct) => ct.to_str().unwrap(),
-        None => "application/octet-stream",
+    cache::hit(&entry.key);
+    GHResponse {
+        status,
+        body,
+        ctype: content_type,
+        headers,
+    }
+    .into_response()
+}
+
+/// The compressed variant GET and HEAD answer `req` with, and its ETag.
+fn negotiated(req: &HeaderMap, meta: &cache::Meta, etag: &str) -> Option<(Encoding, String)> {
+    Encoding::negotiate(req)
+        .filter(|_| encoding::compressible(meta) && !req.contains_key(header::RANGE))
+        .map(|encoding| (encoding, encoding.etag(etag)))
+}
+
+/// Turns a fresh 200 into a 304 when the client already holds it. The cache
+/// fill carries on after the body is dropped.
+fn not_modified(res: Response, req: &HeaderMap) -> Response {
+    let etag = util::get_header(res.headers(), header::ETAG);
+    let last_modified = util::get_header(res.headers(), header::LAST_MODIFIED);
+    if res.status() != StatusCode::OK
+        || !conditional::not_modified(req, etag.as_deref(), last_modified.as_deref())
+    {
+        return res;
+    }
+    let (mut parts, _) = res.into_parts();
+    parts.status = StatusCode::NOT_MODIFIED;
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, axum::body::boxed(axum::body::Empty::new()))
+}
+
+/// Replaces the upstream Cache-Control with the entry's remaining freshness.
+fn cache_control(headers: &mut HeaderMap, meta: &cache::Meta) {
+    if CONFIG.cache.cache_control.is_empty() {
+        return;
+    }
+    let value = match meta.is_immutable() {
+        true => format!("{}, max-age={YEAR}, immutable", CONFIG.cache.cache_control),
+        false => format!(
+            "{}, max-age={}",
+            CONFIG.cache.cache_control,
+            meta.remaining()
+        ),
+    };
+    if let Ok(value) = value.parse() {
+        headers.insert(header::CACHE_CONTROL, value);
+    }
+}
+
+fn server_timing(elapsed: Duration) -> HeaderValue {
+    let value = format!("upstream;dur={:.1}", elapsed.as_secs_f64() * 1000.0);
+    HeaderValue::from_str(&value).unwrap()
+}
+
+async fn stale_if_error(
+    stale: Option<cache::Entry>,
+    e: CustomError,
+    req: &HeaderMap,
+) -> Result<Response, CustomError> {
+    match stale {
+        Some(entry) if entry.meta.is_stale_within(CONFIG.cache.stale_if_error) => {
+            warn!("{:?}: serving stale copy, {e}", entry.key.url);
+            Ok(cached(entry, XCache::Stale(REVALIDATION_FAILED), req).await)
+        }
+        _ => Err(e),
+    }
+}
+
+/// Fetches `key` from upstream, revalidating `stale` when there is one, and
+/// reports the result to the requests waiting on `guard`.
+async fn update(
+    req: &Request,
+    key: &cache::Key,
+    stale: Option<&cache::Entry>,
+    guard: Option<Guard>,
+) -> Result<Update, CustomError> {
+    let start = Instant::now();
+    let res = match fetch(req, stale.map(|entry| &entry.meta)).await {
+        Ok(res) if stale.is_some() && res.status().is_server_error() => Err(CustomError::new(
+            format!("upstream responded {}", res.status()),
+            StatusCode::BAD_GATEWAY,
+        )),
+        res => res,
     };
-    let res = req.get().await?;
-    let content = res.bytes().await.unwrap();
-    if is_success {
-        if let Ok(_) = fs::write(&filepath, &content).await {
-            fs::write(&typepath, &content_type).await.ok();
+    let res = match res {
+        Ok(res) => res,
+        Err(e) => return Err(fail(guard, e)),
+    };
+    if let Some(entry) = stale.filter(|_| res.status() == StatusCode::NOT_MODIFIED) {
+        debug!("{:?} revalidated", key.url);
+        let mut meta = entry.meta.clone();
+        meta.revalidated(res.headers());
+        cache::save(&entry.key, &meta).await;
+        if let Some(guard) = guard {
+            guard.finish(Outcome::Cached);
+        }
+        return Ok(Update::NotModified(meta, start.elapsed()));
+    }
+    let status = res.status();
+    let meta = cache::Meta::new(req.url(), status.as_u16(), res.headers());
+    let content_type = meta.content_type.clone();
+    let mut headers = meta.header_map();
+    for (name, value) in [
+        (header::ETAG, &meta.etag),
+        (header::LAST_MODIFIED, &meta.last_modified),
+    ] {
+        if let Some(value) = value.as_ref().and_then(|value| value.parse().ok()) {
+            headers.insert(name, value);
         }
     }
+    headers.insert("x-cache", XCache::Miss.header());
+    headers.insert("server-timing", server_timing(start.elapsed()));
+    let max = cache::rule::policy(req.url()).max;
+    let body = match cache::is_storable(&meta) {
+        true => limited(res, max),
+        false => Ok(Body::Passthrough(
+            passthrough(res.bytes_stream()),
+            "upstream forbids storing it".to_string(),
+        )),
+    };
+    let upstream = match body {
+        Ok(Body::Cacheable(upstream)) => upstream,
+        Ok(Body::Passthrough(upstream, reason)) => {
+            warn!("{:?} is not cached: {reason}", key.url);
+            cache::remove(&key.name()).await;
+            if let Some(guard) = guard {
+                guard.finish(Outcome::Uncached);
+            }
+            if let Ok(reason) = HeaderValue::from_str(&reason) {
+                headers.insert("x-cache-bypass", reason);
+            }
+            return Ok(Update::Fetched(
+                GHResponse {
+                    status,
+                    body: StreamBody::new(upstream),
+                    ctype: content_type,
+                    headers,
+                }
+                .into_response(),
+            ));
+        }
+        Err(e) => return Err(fail(guard, e)),
+    };
+    let body = if status.is_success() {
+        cache::remove(&key.negative().name()).await;
+        cache_control(&mut headers, &meta);
+        if encoding::varies(&meta) {
+            headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+        }
+        let limits = (max, passthrough_max().max(max));
+        StreamBody::new(cache::tee(upstream, key.clone(), meta, guard, limits)).into_response()
+    } else if meta.is_negative() {
+        cache::remove(&key.name()).await;
+        cache_control(&mut headers, &meta);
+        let limits = (max, passthrough_max().max(max));
+        StreamBody::new(cache::tee(upstream, key.negative(), meta, guard, limits)).into_response()
+    } else {
+        if let Some(guard) = guard {
+            guard.finish(Outcome::Uncached);
+        }
+        StreamBody::new(passthrough(upstream)).into_response()
+    };
+
+    Ok(Update::Fetched(
+        GHResponse {
+            status,
+            body,
+            ctype: content_type,
+            headers,
+        }
+        .into_response(),
+    ))
+}
 
+/// Forwards a request that is not answered from the cache, such as a range
+/// of an uncached file or a file no rule lets us cache, without caching it.
+async fn uncached(req: &Request, headers: &HeaderMap) -> Result<Response, CustomError> {
+    let start = Instant::now();
+    let res = req
+        .forward(headers, &[header::RANGE, header::IF_RANGE])
+        .await?;
+    if let Some(content_length) = res.content_length() {
+        check_size(content_length, passthrough_max())?;
+    }
+    let status = res.status();
+    let meta = cache::Meta::new(req.url(), status.as_u16(), res.headers());
+    let mut headers = meta.header_map();
+    if let Some(content_range) = res.headers().get(header::CONTENT_RANGE) {
+        headers.insert(header::CONTENT_RANGE, content_range.clone());
+    }
+    headers.insert("x-cache", XCache::Miss.header());
+    headers.insert("server-timing", server_timing(start.elapsed()));
     Ok(GHResponse {
-        body: content,
-        ctype: content_type.to_string(),
+        status,
+        body: StreamBody::new(passthrough(res.bytes_stream())),
+        ctype: meta.content_type,
+        headers,
     }
     .into_response())
 }
+
+async fn fetch(
+    req: &Request,
+    stale: Option<&cache::Meta>,
+) -> Result<reqwest::Response, CustomError> {
+    match stale {
+        Some(meta) => req.revalidate(meta).await,
+        None => req.get().await,
+    }
+}
+
+/// Sorts an upstream body by its declared size: up to `max` it is cached, up
+/// to `CONFIG.passthrough_max` it is streamed through uncached, beyond that
+/// it is refused. A body without a length is streamed into the cache, and
+/// the fill applies the same limits as the bytes arrive.
+fn limited(res: reqwest::Response, max: u64) -> Result<Body, CustomError> {
+    let Some(content_length) = res.content_length() else {
+        return Ok(Body::Cacheable(res.bytes_stream().boxed()));
+    };
+    match check_size(content_length, max) {
+        Ok(_) => Ok(Body::Cacheable(res.bytes_stream().boxed())),
+        Err(e) => {
+            check_size(content_length, passthrough_max()).map_err(|_| e.clone())?;
+            Ok(Body::Passthrough(
+                passthrough(res.bytes_stream()),
+                e.to_string(),
+            ))
+        }
+    }
+}
+
+/// Streams `upstream`, failing the response once it outgrows
+/// `CONFIG.passthrough_max`.
+fn passthrough<S>(upstream: S) -> Passthrough
+where
+    S: Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+{
+    let max = passthrough_max();
+    let mut total = 0;
+    upstream
+        .map(move |chunk| {
+            let chunk = chunk.map_err(axum::Error::new)?;
+            total += chunk.len() as u64;
+            match total > max {
+                true => Err(axum::Error::new(format!("file size > {}", size(max)))),
+                false => Ok(chunk),
+            }
+        })
+        .boxed()
+}
+
+fn passthrough_max() -> u64 {
+    CONFIG.passthrough_max.max(CONFIG.file_max)
+}
+
+fn check_size(content_length: u64, max: u64) -> Result<(), CustomError> {
+    if content_length > max {
+        let reason = format!("file size: {} > {}", size(content_length), size(max));
+        return Err(CustomError::new(reason, StatusCode::PAYLOAD_TOO_LARGE));
+    }
+    Ok(())
+}
+
+fn size(bytes: u64) -> byte_unit::AdjustedByte {
+    byte_unit::Byte::from_bytes(bytes).get_appropriate_unit(true)
+}
+
+fn fail(guard: Option<Guard>, e: CustomError) -> CustomError {
+    if let Some(guard) = guard {
+        guard.finish(Outcome::Failed(e.clone()));
+    }
+    e
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caps_max_age() {
+        assert_eq!(
+            capped("public, max-age=31536000, immutable", 60),
+            "public, max-age=60"
+        );
+        assert_eq!(capped("private, max-age=30", 60), "private, max-age=30");
+        assert_eq!(capped("no-cache", 60), "no-cache");
+    }
+}
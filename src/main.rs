@@ -16,12 +16,15 @@ use tokio::task::AbortHandle;
 #[macro_use]
 extern crate tracing;
 
+mod admin;
+mod cache;
 mod config;
 mod error;
 mod gh;
 mod task;
 mod trace;
 mod util;
+mod webhook;
 
 pub use config::CONFIG;
 pub use error::CustomError;
@@ -34,12 +37,17 @@ async fn main() -> std::io::Result<()> {
     let client = Arc::new(reqwest::Client::new());
     let (task_jh, task_cancel) = task::init_background_task();
     let task_jh_state = Arc::new(task_jh.abort_handle());
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/alive", get(alive))
         .with_state(task_jh_state)
-        .nest("/gh", gh::routes())
-        .with_state(client)
-        .layer(trace::TraceLayer);
+        .nest("/gh", gh::routes());
+    if config::CONFIG.webhook.secret.is_some() {
+        app = app.nest("/webhook", webhook::routes());
+    }
+    if let Some(token) = &config::CONFIG.admin.token {
+        app = app.nest("/admin", admin::routes(token));
+    }
+    let app = app.with_state(client).layer(trace::TraceLayer);
 
     let server = axum::Server::bind(&config::CONFIG.addr)
         .serve(app.into_make_service_with_connect_info::<std::net::SocketAddr>());
@@ -73,7 +81,7 @@ async fn shutdown_signal() -> std::io::Result<()> {
     Ok(())
 }
 
-async fn alive<'a>(State(task): State<Arc<AbortHandle>>) -> Result<Response, CustomError> {
+async fn alive(State(task): State<Arc<AbortHandle>>) -> Result<Response, CustomError> {
     if task.is_finished() {
         error!("background task failed");
         return Err(CustomError::reason("background task failed"));
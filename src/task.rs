@@ -1,15 +1,17 @@
-use std::io::ErrorKind;
-
 use tokio::{
-    fs::{create_dir_all, read_dir, DirEntry},
+    fs::create_dir_all,
     task,
-    time::{self, sleep},
+    time::{self, sleep, Instant},
 };
 use tokio_util::sync::CancellationToken;
 
-use crate::util;
+use crate::cache;
 use crate::CONFIG;
 
+/// How often shared storage is listed for entries other instances added or
+/// removed; purges always list it first.
+const SYNC_INTERVAL: time::Duration = time::Duration::from_secs(600);
+
 pub fn init_background_task() -> (task::JoinHandle<()>, CancellationToken) {
     let cancel = CancellationToken::new();
 
@@ -18,60 +20,52 @@ pub fn init_background_task() -> (task::JoinHandle<()>, CancellationToken) {
 
 async fn background_task(stop_signal: CancellationToken) {
     info!("Starting Background Task");
-    let cache_time = chrono::Duration::seconds(CONFIG.cache.expiry as i64);
+    if let Err(e) = create_dir_all(&CONFIG.cache.path).await {
+        error!("mkdir {:?}: {e}", CONFIG.cache.path);
+    }
+    cache::migrate(&CONFIG.cache.path).await;
+    cache::sweep().await;
+    cache::index::load().await;
+    let mut stats = cache::stats();
+    let mut synced: Option<Instant> = None;
     loop {
-        let mut entries = match read_dir(&CONFIG.cache.path).await {
-            Ok(entries) => entries,
-            Err(e) => {
-                error!("{:?}:{e}", e.kind());
-                if e.kind() == ErrorKind::NotFound {
-                    error!("mkdir: {:?}", CONFIG.cache.path);
-                    create_dir_all(&CONFIG.cache.path).await.ok();
-                }
-                continue;
-            }
-        };
         let mut cache_size = 0;
-        let mut files: Vec<(DirEntry, chrono::DateTime<chrono::Utc>, u64)> = Vec::new();
-        while let Ok(Some(entry)) = entries.next_entry().await {
-            if let Ok(metadata) = entry.metadata().await {
-                if metadata.is_file() {
-                    let filepath = entry.path();
-                    if let Some(extension) = filepath.extension() {
-                        if extension == "type" {
-                            continue;
-                        }
-                    }
-                    let create_date = util::create_date(&metadata);
-                    let duration = chrono::Utc::now() - create_date;
-                    if duration > cache_time {
-                        warn!(
-                            "{:?} cache has expired, {duration:?} > {cache_time:?}",
-                            entry.file_name()
-                        );
-                        util::remove_file(&filepath).await;
-                        continue;
-                    }
-                    let file_size = metadata.len();
-                    cache_size += file_size;
-                    files.push((entry, create_date, file_size));
-                }
+        let mut files: Vec<(String, cache::Meta)> = Vec::new();
+        if synced.is_none_or(|synced| synced.elapsed() >= SYNC_INTERVAL) {
+            cache::index::sync().await;
+            synced = Some(Instant::now());
+        }
+        for (name, meta) in cache::index::entries().await {
+            let expired = if meta.is_negative() {
+                !meta.is_fresh()
+            } else {
+                !meta.has_validators() && !meta.is_stale_within(CONFIG.cache.stale_if_error)
+            };
+            if expired {
+                warn!("{name} cache has expired and cannot be revalidated");
+                cache::remove(&name).await;
+                continue;
             }
+            cache_size += meta.size;
+            files.push((name, meta));
         }
         if cache_size > CONFIG.cache.max {
             warn!("Exceed the maximum cache");
-            debug!("{files:?}");
-            files.sort_by(|a, b| a.1.cmp(&b.1));
-            debug!("{files:?}");
-            for (file, _, size) in files.iter() {
-                warn!("delete file {:?}", file.file_name());
-                util::remove_file(&file.path()).await;
-                cache_size -= size;
+            cache::eviction_order(&mut files);
+            for (name, meta) in files.iter() {
+                warn!("delete {name}");
+                cache::remove(name).await;
+                cache_size -= meta.size;
                 if cache_size <= CONFIG.cache.max {
                     break;
                 }
             }
         }
+        cache::index::maintain().await;
+        if cache::stats() != stats {
+            stats = cache::stats();
+            info!("cache lookups: {stats}");
+        }
         tokio::select! {
             _ = sleep(time::Duration::from_secs(10)) => {
                 continue;
@@ -79,6 +73,7 @@ async fn background_task(stop_signal: CancellationToken) {
 
             _ = stop_signal.cancelled() => {
                 info!("gracefully shutting down background task");
+                cache::index::close().await;
                 break;
             }
         };
@@ -87,8 +87,8 @@ where
         let method = req.method().to_string();
         let path = req.uri().path().to_string();
         let headers = req.headers();
-        let referer = util::get_header(&headers, header::REFERER).unwrap_or("-".to_string());
-        let ua = util::get_ua(&headers);
+        let referer = util::get_header(headers, header::REFERER).unwrap_or("-".to_string());
+        let ua = util::get_ua(headers);
         let response_future = self.inner.call(req);
         TraceFuture {
             response_future,
@@ -1,14 +1,15 @@
-use std::{ffi::OsStr, fs::Metadata, path::PathBuf};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
 
 use axum::{
     extract::ConnectInfo,
     http::{header, HeaderMap, Request},
 };
-use chrono::{DateTime, Utc};
-use mime_guess;
 use tokio::fs;
 
-pub fn typepath(filepath: &PathBuf) -> PathBuf {
+pub fn typepath(filepath: &Path) -> PathBuf {
     filepath.with_extension(format!(
         "{}.type",
         filepath
@@ -29,15 +30,6 @@ pub async fn content_type_typepath(typepath: &PathBuf) -> String {
         )
 }
 
-pub async fn remove_file(filepath: &PathBuf) {
-    fs::remove_file(filepath).await.ok();
-    fs::remove_file(typepath(filepath)).await.ok();
-}
-
-pub fn create_date(metadata: &Metadata) -> DateTime<Utc> {
-    DateTime::from(metadata.created().unwrap_or(metadata.modified().unwrap()))
-}
-
 pub fn get_ip<B>(req: &Request<B>) -> String {
     let headers = req.headers();
     if let Some(ip) = get_header(headers, "X-Forwarded-For") {
@@ -70,3 +62,87 @@ pub fn get_ua(headers: &HeaderMap) -> String {
         None => "-".to_string(),
     }
 }
+
+/// Matches `text` against a glob where `*` stays within a path segment,
+/// `**` crosses segments and `?` is any one character but `/`.
+pub fn glob(pattern: &str, text: &str) -> bool {
+    let (p, t) = (pattern.as_bytes(), text.as_bytes());
+    // matched[i][j]: whether `p[i..]` matches `t[j..]`, filled from the end.
+    let mut matched = vec![vec![false; t.len() + 1]; p.len() + 1];
+    matched[p.len()][t.len()] = true;
+    for i in (0..p.len()).rev() {
+        // Whether `p[i + 3..]` matches after some `/` at or past `j`.
+        let mut dirs = false;
+        for j in (0..=t.len()).rev() {
+            let c = t.get(j).copied();
+            matched[i][j] = match &p[i..] {
+                [b'*', b'*', b'/', ..] => {
+                    dirs = dirs || (c == Some(b'/') && matched[i + 3][j + 1]);
+                    matched[i + 3][j] || dirs
+                }
+                [b'*', b'*', ..] => matched[i + 2][j] || (c.is_some() && matched[i][j + 1]),
+                [b'*', ..] => {
+                    matched[i + 1][j] || (c.is_some_and(|c| c != b'/') && matched[i][j + 1])
+                }
+                [b'?', ..] => c.is_some_and(|c| c != b'/') && matched[i + 1][j + 1],
+                _ => c == Some(p[i]) && matched[i + 1][j + 1],
+            };
+        }
+    }
+    matched[0][0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob;
+
+    #[test]
+    fn star_stays_in_segment() {
+        assert!(glob("*.md", "README.md"));
+        assert!(!glob("*.md", "docs/README.md"));
+        assert!(glob("docs/*", "docs/a.md"));
+        assert!(!glob("docs/*", "docs/a/b.md"));
+        assert!(glob("*", ""));
+    }
+
+    #[test]
+    fn double_star_crosses_segments() {
+        assert!(glob("**", "a/b/c"));
+        assert!(glob("docs/**", "docs/a/b.md"));
+        assert!(glob("**.md", "docs/a/b.md"));
+        assert!(!glob("docs/**", "src/a.md"));
+    }
+
+    #[test]
+    fn double_star_slash_matches_zero_dirs() {
+        assert!(glob("**/*.md", "a.md"));
+        assert!(glob("**/*.md", "x/y/a.md"));
+        assert!(glob("docs/**/a.md", "docs/a.md"));
+        assert!(glob("docs/**/a.md", "docs/x/y/a.md"));
+        assert!(!glob("docs/**/a.md", "docs/xa.md"));
+    }
+
+    #[test]
+    fn question_mark_is_one_char() {
+        assert!(glob("v?.txt", "v1.txt"));
+        assert!(!glob("v?.txt", "v10.txt"));
+        assert!(!glob("a?b", "a/b"));
+        assert!(!glob("?", ""));
+    }
+
+    #[test]
+    fn many_stars_stay_fast() {
+        let path = "a".repeat(60);
+        let start = std::time::Instant::now();
+        assert!(!glob("**a**a**a**a**a**b", &path));
+        assert!(!glob("*a*a*a*a*a*a*a*b", &path));
+        assert!(start.elapsed() < std::time::Duration::from_millis(50));
+    }
+
+    #[test]
+    fn literals_match_exactly() {
+        assert!(glob("main", "main"));
+        assert!(!glob("main", "main2"));
+        assert!(!glob("main", "mai"));
+    }
+}
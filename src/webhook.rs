@@ -0,0 +1,181 @@
+use std::collections::HashSet;
+
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Router,
+};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::{cache, gh, util, CustomError, CONFIG};
+
+/// A push webhook lists at most this many commits; a longer push may have
+/// touched files that are not listed.
+const MAX_COMMITS: usize = 2048;
+
+#[derive(Deserialize)]
+struct Push {
+    #[serde(rename = "ref")]
+    reference: String,
+    repository: Repository,
+    #[serde(default)]
+    commits: Vec<Commit>,
+    #[serde(default)]
+    created: bool,
+    #[serde(default)]
+    deleted: bool,
+    #[serde(default)]
+    forced: bool,
+}
+
+#[derive(Deserialize)]
+struct Repository {
+    full_name: String,
+}
+
+#[derive(Deserialize)]
+struct Commit {
+    #[serde(default)]
+    added: Vec<String>,
+    #[serde(default)]
+    removed: Vec<String>,
+    #[serde(default)]
+    modified: Vec<String>,
+}
+
+pub fn routes<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new().route("/github", post(github))
+}
+
+async fn github(headers: HeaderMap, body: Bytes) -> Result<Response, CustomError> {
+    verify(
+        CONFIG.webhook.secret.as_deref().unwrap_or_default(),
+        &headers,
+        &body,
+    )?;
+    let event = util::get_header(&headers, "x-github-event").unwrap_or_default();
+    match event.as_str() {
+        "ping" => Ok("pong".into_response()),
+        "push" => {
+            let push: Push = serde_json::from_slice(&body)
+                .map_err(|e| CustomError::new(e.to_string(), StatusCode::BAD_REQUEST))?;
+            let purged = purge(&push).await;
+            Ok(format!("purged {purged} entries").into_response())
+        }
+        _ => Ok(StatusCode::NO_CONTENT.into_response()),
+    }
+}
+
+fn verify(secret: &str, headers: &HeaderMap, body: &[u8]) -> Result<(), CustomError> {
+    let signature = util::get_header(headers, "x-hub-signature-256")
+        .and_then(|signature| hex::decode(signature.strip_prefix("sha256=")?).ok());
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(body);
+    match signature {
+        Some(signature) if !secret.is_empty() && mac.verify_slice(&signature).is_ok() => Ok(()),
+        _ => Err(CustomError::new(
+            "invalid X-Hub-Signature-256",
+            StatusCode::UNAUTHORIZED,
+        )),
+    }
+}
+
+/// Removes what the push changed below `owner/repo/ref`: the listed files
+/// when the commits name all of them, everything under the ref otherwise.
+async fn purge(push: &Push) -> usize {
+    let Some((owner, repo)) = push.repository.full_name.split_once('/') else {
+        return 0;
+    };
+    let short = push
+        .reference
+        .strip_prefix("refs/heads/")
+        .or_else(|| push.reference.strip_prefix("refs/tags/"))
+        .unwrap_or(&push.reference);
+    gh::forget_ref(owner, repo, short);
+    // raw.githubusercontent.com takes both `main/…` and `refs/heads/main/…`.
+    let prefixes: Vec<String> = [short, push.reference.as_str()]
+        .iter()
+        .map(|reference| gh::url(&format!("{owner}/{repo}/{reference}/")).to_lowercase())
+        .collect();
+    let complete = !push.commits.is_empty()
+        && push.commits.len() < MAX_COMMITS
+        && !(push.created || push.deleted || push.forced);
+    let purged = if complete {
+        let files: HashSet<String> = push
+            .commits
+            .iter()
+            .flat_map(|commit| [&commit.added, &commit.removed, &commit.modified])
+            .flatten()
+            .flat_map(|file| {
+                prefixes
+                    .iter()
+                    .map(move |prefix| format!("{prefix}{}", file.to_lowercase()))
+            })
+            .collect();
+        cache::purge(|meta| files.contains(&meta.url.to_lowercase())).await
+    } else {
+        cache::purge(|meta| {
+            let url = meta.url.to_lowercase();
+            prefixes.iter().any(|prefix| url.starts_with(prefix))
+        })
+        .await
+    };
+    info!(
+        "push to {} {}: purged {purged} entries",
+        push.repository.full_name, push.reference
+    );
+    purged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &[u8] = br#"{"zen":"Keep it logically awesome."}"#;
+
+    fn signed(secret: &str, signature: impl FnOnce(String) -> String) -> HeaderMap {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(BODY);
+        let digest = hex::encode(mac.finalize().into_bytes());
+        let mut headers = HeaderMap::new();
+        headers.insert("x-hub-signature-256", signature(digest).parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn accepts_a_valid_signature() {
+        let headers = signed("secret", |digest| format!("sha256={digest}"));
+        assert!(verify("secret", &headers, BODY).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_bad_signature() {
+        let headers = signed("other", |digest| format!("sha256={digest}"));
+        assert!(verify("secret", &headers, BODY).is_err());
+        let headers = signed("secret", |digest| format!("sha256={digest}"));
+        assert!(verify("secret", &headers, b"{}").is_err());
+        assert!(verify("secret", &HeaderMap::new(), BODY).is_err());
+    }
+
+    #[test]
+    fn rejects_a_signature_without_prefix() {
+        let headers = signed("secret", |digest| digest);
+        assert!(verify("secret", &headers, BODY).is_err());
+        let headers = signed("secret", |digest| format!("sha1={digest}"));
+        assert!(verify("secret", &headers, BODY).is_err());
+    }
+
+    #[test]
+    fn rejects_everything_without_a_secret() {
+        let headers = signed("", |digest| format!("sha256={digest}"));
+        assert!(verify("", &headers, BODY).is_err());
+    }
+}